@@ -18,6 +18,7 @@ impl BanReleaseStore {
         Ok(store)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn upsert_job(
         &self,
         chat_id: i64,
@@ -141,7 +142,7 @@ fn init_db(path: &str) -> Result<(), rusqlite::Error> {
     Ok(())
 }
 
-fn open_db(path: &str) -> Result<Connection, rusqlite::Error> {
+pub(crate) fn open_db(path: &str) -> Result<Connection, rusqlite::Error> {
     let conn = Connection::open(path)?;
     conn.pragma_update(None, "journal_mode", "WAL")?;
     conn.pragma_update(None, "busy_timeout", "3000")?;
@@ -2,23 +2,83 @@ use std::collections::HashMap;
 use std::error::Error;
 use std::sync::Arc;
 
-use captcha::Captcha;
-use captcha::filters::Noise;
+use rand::distributions::Alphanumeric;
 use rand::{Rng, seq::SliceRandom};
-use teloxide::types::{ChatId, MessageId, UserId};
+use serde::{Deserialize, Serialize};
+use teloxide::types::{
+    ChatId, ChatPermissions, InlineKeyboardButton, InlineKeyboardMarkup, MessageId, UserId,
+};
 use tokio::sync::Mutex;
 
+use crate::captcha_alphabet::{CaptchaAlphabet, codes_confusable};
+use crate::captcha_emoji::EmojiChallenge;
+use crate::captcha_grid::GridChallenge;
+use crate::captcha_keypad::KeypadChallenge;
+use crate::captcha_labels::obfuscate_label;
+use crate::captcha_math::{MathChallenge, PollChallenge};
+use crate::captcha_pool::PoolKey;
+use crate::captcha_private::PrivateVerifyStub;
+use crate::captcha_questions::QuestionChallenge;
 use crate::captcha_quotes::CAPTCHA_QUOTES;
-use crate::utils::{escape_html, format_user_display, format_user_name};
+use crate::captcha_render::{CaptchaFonts, render_captcha_gif, render_captcha_png};
+use crate::captcha_webapp::WebAppChallenge;
+use crate::config::Config;
+use crate::utils::{escape_html, format_user_display, format_user_name, sanitize_log_text};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PendingCaptcha {
     pub code: String,
+    /// Name of the [`ChallengeProvider`] that created this challenge.
+    #[serde(default = "default_provider_name", alias = "mode")]
+    pub provider: String,
+    /// Provider-specific state; opaque to the handlers.
+    #[serde(default)]
+    pub provider_data: serde_json::Value,
     pub captcha_message_id: MessageId,
+    /// Random token embedded in the callback data of this session's buttons.
+    #[serde(default)]
+    pub nonce: String,
     pub options: Vec<String>,
+    #[serde(default)]
+    pub prompt: Option<String>,
+    #[serde(default)]
+    pub poll_id: Option<String>,
+    /// Voice message sent by the "🔊 Audio" button, replaced whenever the code changes.
+    #[serde(default)]
+    pub audio_message_id: Option<MessageId>,
+    /// "🔄 Gambar baru" presses left; refreshing does not cost an attempt.
+    #[serde(default)]
+    pub refreshes_left: usize,
+    #[serde(default)]
+    pub refreshes_total: usize,
     pub attempts_left: usize,
     pub attempts_total: usize,
     pub remaining_secs: u64,
+    pub expires_at: i64,
+    /// When the current challenge was shown (unix millis); reset whenever the code rotates.
+    #[serde(default)]
+    pub sent_at_ms: i64,
+    /// When the latest answer arrived (unix millis).
+    #[serde(default)]
+    pub last_answer_at_ms: Option<i64>,
+    /// Whether the code has to be answered with matching letter case.
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Group of a join request whose captcha runs in the applicant's private chat. Passing
+    /// approves the request and failing declines it; the user is never restricted.
+    #[serde(default)]
+    pub join_request_chat: Option<ChatId>,
+    /// Group whose captcha was moved into the user's private chat through the
+    /// "🔐 Verifikasi" deep link. The outcome applies to that group.
+    #[serde(default)]
+    pub private_origin: Option<PrivateOrigin>,
+    /// Light challenge shown because the user was verified in a related chat. Passing it
+    /// does not renew that trust.
+    #[serde(default)]
+    pub trust_check: bool,
+    /// Joined through an invite link with the strict policy: one attempt, no refreshes.
+    #[serde(default)]
+    pub strict: bool,
     pub user_display: String,
     pub user_name: String,
     pub user_username: Option<String>,
@@ -26,58 +86,487 @@ pub struct PendingCaptcha {
     pub chat_username: Option<String>,
 }
 
+impl PendingCaptcha {
+    /// Time between showing the current challenge and the latest answer. `None` for
+    /// sessions saved before send times were recorded.
+    pub fn solve_ms(&self) -> Option<i64> {
+        let answered = self.last_answer_at_ms?;
+        (self.sent_at_ms > 0).then(|| answered - self.sent_at_ms)
+    }
+
+    /// Chat the captcha guards: the group of a join request or of a captcha moved to the
+    /// private chat, otherwise `chat_id`, the chat the captcha was sent in.
+    pub fn guarded_chat(&self, chat_id: ChatId) -> ChatId {
+        self.join_request_chat
+            .or(self.private_origin.map(|origin| origin.chat_id))
+            .unwrap_or(chat_id)
+    }
+}
+
+/// Where a captcha moved to the private chat came from.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct PrivateOrigin {
+    pub chat_id: ChatId,
+    /// The "🔐 Verifikasi" message left in the group, removed once the captcha ends.
+    pub stub_message_id: MessageId,
+}
+
 pub type CaptchaKey = (ChatId, UserId);
 pub type SharedState = Arc<Mutex<HashMap<CaptchaKey, PendingCaptcha>>>;
 
+pub struct CaptchaChallenge {
+    pub code: String,
+    /// Photo (PNG) or animation (GIF) bytes, depending on the provider's message kind.
+    pub media: Option<Vec<u8>>,
+    pub options: Vec<String>,
+    pub prompt: Option<String>,
+    pub data: serde_json::Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptchaMessageKind {
+    Photo,
+    Animation,
+    Text,
+    Poll,
+}
+
+/// A kind of captcha challenge. Providers are stateless: everything a running challenge
+/// needs is kept in [`PendingCaptcha`], with `provider_data` for anything provider-specific.
+pub trait ChallengeProvider: Send + Sync {
+    /// Name used by `CAPTCHA_MODE` and stored with pending sessions.
+    fn name(&self) -> &'static str;
+
+    /// Extra names accepted by `CAPTCHA_MODE`.
+    fn aliases(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind;
+
+    /// Whether the provider can serve this chat. Unavailable providers fall back to the
+    /// image captcha.
+    fn available(&self, _config: &Config, _chat_id: ChatId) -> bool {
+        true
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>>;
+
+    /// Fresh challenge after a wrong answer.
+    fn regenerate(
+        &self,
+        _pending: &PendingCaptcha,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        self.generate(config, chat_id)
+    }
+
+    /// Instruction line shown in the captcha caption (HTML).
+    fn instruction(&self, prompt: Option<&str>) -> String;
+
+    /// Answer buttons plus the audio/refresh controls, bound to the session `nonce`.
+    /// `refresh` is whether the session still has refreshes left.
+    fn keyboard(
+        &self,
+        options: &[String],
+        config: &Config,
+        nonce: &str,
+        refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        build_captcha_keyboard(
+            options,
+            config.captcha_option_digits_to_emoji,
+            config.captcha_option_obfuscate,
+            nonce,
+            self.controls(config, refresh),
+        )
+    }
+
+    fn controls(&self, config: &Config, refresh: bool) -> CaptchaControls {
+        CaptchaControls {
+            audio: self.offers_audio(config),
+            refresh: refresh && self.offers_refresh(),
+        }
+    }
+
+    /// Whether the user may swap the image for a new one without losing an attempt.
+    fn offers_refresh(&self) -> bool {
+        matches!(
+            self.message_kind(),
+            CaptchaMessageKind::Photo | CaptchaMessageKind::Animation
+        )
+    }
+
+    /// Whether `code` can be read out by the audio captcha. Codes that are themselves the
+    /// answer to a question (math, emoji, question bank) must not be.
+    fn speaks_code(&self) -> bool {
+        false
+    }
+
+    /// Whether the "🔊 Audio" button is shown.
+    fn offers_audio(&self, config: &Config) -> bool {
+        self.speaks_code() && config.captcha_voice.is_some()
+    }
+
+    /// Whether answers must match the code's letter case. Only codes drawn from the
+    /// configured alphabet honour `CAPTCHA_CASE_SENSITIVE`.
+    fn case_sensitive(&self, _config: &Config) -> bool {
+        false
+    }
+
+    fn check_answer(&self, pending: &PendingCaptcha, answer: &str) -> bool {
+        code_matches(pending, answer)
+    }
+
+    /// Whether answers are typed into the chat instead of picked from buttons.
+    fn accepts_text(&self) -> bool {
+        false
+    }
+
+    /// What a group joiner may still do while the captcha runs. Typed answers are sent as
+    /// messages, so those joiners keep text messages.
+    fn restricted_permissions(&self) -> ChatPermissions {
+        if self.accepts_text() {
+            ChatPermissions::SEND_MESSAGES
+        } else {
+            ChatPermissions::empty()
+        }
+    }
+
+    /// Handles a press on one of the provider's own buttons (`captcha-press:` callbacks),
+    /// keeping any partial input in `provider_data`. Returns the answer to check once the
+    /// input is complete; `None` only redraws the captcha message.
+    fn on_press(&self, _pending: &mut PendingCaptcha, _payload: &str) -> Option<String> {
+        None
+    }
+
+    /// Caption line showing input entered so far, for providers that build the answer
+    /// from several button presses. `data` is the session's `provider_data`.
+    fn input_echo(&self, _data: &serde_json::Value, _code: &str) -> Option<String> {
+        None
+    }
+
+    /// How `/start verify_<nonce>` in the bot's private chat continues the challenge, for
+    /// providers that run there instead of in the group.
+    fn private_start(&self) -> Option<PrivateStart> {
+        None
+    }
+}
+
+/// What the bot does when a user opens its private chat through a verification deep link.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PrivateStart {
+    /// Send the Mini App button of a web app captcha.
+    WebAppButton,
+    /// Send the chat's real challenge and move the session into the private chat.
+    MoveChallenge,
+}
+
+static CHALLENGE_PROVIDERS: &[&dyn ChallengeProvider] = &[
+    &ImageChallenge,
+    &AnimatedChallenge,
+    &MathChallenge,
+    &EmojiChallenge,
+    &PollChallenge,
+    &QuestionChallenge,
+    &TypedChallenge,
+    &KeypadChallenge,
+    &GridChallenge,
+    &WebAppChallenge,
+];
+
+pub fn challenge_provider(name: &str) -> Option<&'static dyn ChallengeProvider> {
+    let name = name.trim();
+    CHALLENGE_PROVIDERS.iter().copied().find(|provider| {
+        provider.name().eq_ignore_ascii_case(name)
+            || provider
+                .aliases()
+                .iter()
+                .any(|alias| alias.eq_ignore_ascii_case(name))
+    })
+}
+
+/// Providers that only stand in for a challenge shown elsewhere. Pending sessions can use
+/// them, `CAPTCHA_MODE` cannot.
+static STUB_PROVIDERS: &[&dyn ChallengeProvider] = &[&PrivateVerifyStub];
+
+/// Provider for a pending session. Sessions stored under an unknown name are treated as
+/// image captchas.
+pub fn pending_provider(pending: &PendingCaptcha) -> &'static dyn ChallengeProvider {
+    challenge_provider(&pending.provider)
+        .or_else(|| {
+            STUB_PROVIDERS
+                .iter()
+                .copied()
+                .find(|provider| provider.name() == pending.provider)
+        })
+        .unwrap_or(&ImageChallenge)
+}
+
+/// Provider actually used in a chat.
+pub fn challenge_provider_for_chat(
+    config: &Config,
+    chat_id: ChatId,
+) -> &'static dyn ChallengeProvider {
+    challenge_provider(config.captcha_mode)
+        .filter(|provider| provider.available(config, chat_id))
+        .unwrap_or(&ImageChallenge)
+}
+
+fn default_provider_name() -> String {
+    ImageChallenge.name().to_string()
+}
+
+/// Code rendered in an image, answered with buttons.
+pub struct ImageChallenge;
+
+impl ChallengeProvider for ImageChallenge {
+    fn name(&self) -> &'static str {
+        "image"
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Photo
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let difficulty = config.captcha_difficulty(chat_id);
+        let alphabet = config.captcha_alphabet(chat_id);
+        let (code, png) = pooled_captcha(config, alphabet, difficulty.len)?;
+        let options = generate_captcha_options(
+            &code,
+            difficulty.option_count,
+            alphabet,
+            self.case_sensitive(config),
+        );
+        Ok(CaptchaChallenge {
+            code,
+            media: Some(png),
+            options,
+            prompt: None,
+            data: serde_json::Value::Null,
+        })
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "💁🏻‍♂️ Pilih jawaban yang benar dari tombol yang tersedia.".to_string()
+    }
+
+    fn speaks_code(&self) -> bool {
+        true
+    }
+
+    fn case_sensitive(&self, config: &Config) -> bool {
+        config.captcha_case_sensitive
+    }
+}
+
+/// Code shown one character at a time in an animated GIF, answered with buttons. No
+/// single frame holds the whole code, which defeats OCR on a screenshot.
+pub struct AnimatedChallenge;
+
+impl ChallengeProvider for AnimatedChallenge {
+    fn name(&self) -> &'static str {
+        "animated"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["gif", "animation"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Animation
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let difficulty = config.captcha_difficulty(chat_id);
+        let alphabet = config.captcha_alphabet(chat_id);
+        let challenge =
+            pooled_challenge(config, PoolKey::Animated(alphabet.clone(), difficulty.len))?;
+        let code = challenge.code;
+        let options = generate_captcha_options(
+            &code,
+            difficulty.option_count,
+            alphabet,
+            self.case_sensitive(config),
+        );
+        Ok(CaptchaChallenge {
+            code,
+            media: challenge.media,
+            options,
+            prompt: None,
+            data: serde_json::Value::Null,
+        })
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "🎞 Huruf muncul bergantian dari kiri ke kanan. Pilih jawaban yang benar dari tombol yang tersedia."
+            .to_string()
+    }
+
+    fn speaks_code(&self) -> bool {
+        true
+    }
+
+    fn case_sensitive(&self, config: &Config) -> bool {
+        config.captcha_case_sensitive
+    }
+}
+
+/// Code rendered in an image that has to be typed into the chat, so there are no buttons
+/// to guess from.
+pub struct TypedChallenge;
+
+impl ChallengeProvider for TypedChallenge {
+    fn name(&self) -> &'static str {
+        "typed"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["type", "text"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Photo
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let difficulty = config.captcha_difficulty(chat_id);
+        let alphabet = config.captcha_alphabet(chat_id);
+        let (code, png) = pooled_captcha(config, alphabet, difficulty.len)?;
+        Ok(CaptchaChallenge {
+            code,
+            media: Some(png),
+            options: Vec::new(),
+            prompt: None,
+            data: serde_json::Value::Null,
+        })
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "⌨️ Ketik kode pada gambar, lalu kirim sebagai pesan di chat ini.".to_string()
+    }
+
+    fn keyboard(
+        &self,
+        _options: &[String],
+        config: &Config,
+        nonce: &str,
+        refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        build_captcha_keyboard(&[], false, false, nonce, self.controls(config, refresh))
+    }
+
+    fn speaks_code(&self) -> bool {
+        true
+    }
+
+    fn case_sensitive(&self, config: &Config) -> bool {
+        config.captcha_case_sensitive
+    }
+
+    fn check_answer(&self, pending: &PendingCaptcha, answer: &str) -> bool {
+        let answer: String = answer.chars().filter(|ch| !ch.is_whitespace()).collect();
+        code_matches(pending, &answer)
+    }
+
+    fn accepts_text(&self) -> bool {
+        true
+    }
+}
+
 pub enum CaptchaCheck {
     NoPending,
+    /// Answered faster than `CAPTCHA_MIN_SOLVE_MS`; handled as a wrong answer.
+    TooFast,
     Wrong,
-    Verified(PendingCaptcha),
+    Verified(Box<PendingCaptcha>),
 }
 
-const CAPTCHA_SAFE_CHARS: &[char] = &[
-    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'M', 'N', 'P', 'Q', 'R', 'T', 'U', 'V', 'W',
-    'X', 'Y', 'Z', '2', '3', '4', '6', '7', '8', '9',
-];
+fn code_matches(pending: &PendingCaptcha, answer: &str) -> bool {
+    if pending.case_sensitive {
+        answer == pending.code
+    } else {
+        answer.eq_ignore_ascii_case(&pending.code)
+    }
+}
 
+/// Pre-rendered challenge from the pool when one is ready, otherwise rendered right here.
+pub fn pooled_challenge(
+    config: &Config,
+    key: PoolKey,
+) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+    if let Some(ready) = config
+        .captcha_pool
+        .as_ref()
+        .and_then(|pool| pool.take(&key))
+    {
+        return Ok(ready);
+    }
+    key.render(
+        &config.captcha_fonts,
+        config.captcha_width,
+        config.captcha_height,
+    )
+}
+
+/// Pre-rendered image code and its PNG, see [`pooled_challenge`].
+pub fn pooled_captcha(
+    config: &Config,
+    alphabet: &CaptchaAlphabet,
+    length: usize,
+) -> Result<(String, Vec<u8>), Box<dyn Error + Send + Sync>> {
+    let challenge = pooled_challenge(config, PoolKey::Image(alphabet.clone(), length))?;
+    Ok((challenge.code, challenge.media.unwrap_or_default()))
+}
+
+/// Random code from `alphabet` rendered by the in-tree renderer.
 pub fn generate_captcha(
+    fonts: &CaptchaFonts,
+    alphabet: &CaptchaAlphabet,
     length: usize,
     width: u32,
     height: u32,
 ) -> Result<(String, Vec<u8>), Box<dyn Error + Send + Sync>> {
-    let safe_width = width.min(399);
-    let safe_height = height.min(299);
-    let mut captcha = Captcha::new();
-    captcha
-        .set_chars(CAPTCHA_SAFE_CHARS)
-        .add_chars(length as u32)
-        .apply_filter(Noise::new(0.4))
-        .view(safe_width, safe_height);
-
-    let mut code = captcha.chars_as_string();
-    if code.is_empty() {
-        let mut fallback = Captcha::new();
-        let supported = fallback.supported_chars();
-        fallback
-            .set_chars(&supported)
-            .add_chars(length as u32)
-            .apply_filter(Noise::new(0.4))
-            .view(safe_width, safe_height);
-        code = fallback.chars_as_string();
-        let png = fallback.as_png().ok_or("failed to render captcha")?;
-        return Ok((code, png));
-    }
-
-    let png = captcha.as_png().ok_or("failed to render captcha")?;
+    let code = alphabet.random_code(length);
+    let png = render_captcha_png(&code, fonts, width, height)?;
     Ok((code, png))
 }
 
-pub fn captcha_caption(
-    user: &teloxide::types::User,
-    remaining_secs: u64,
-    attempts_left: usize,
-    attempts_total: usize,
-) -> String {
+/// Random code from `alphabet` as an animated GIF.
+pub fn generate_animated_captcha(
+    fonts: &CaptchaFonts,
+    alphabet: &CaptchaAlphabet,
+    length: usize,
+    width: u32,
+    height: u32,
+) -> Result<(String, Vec<u8>), Box<dyn Error + Send + Sync>> {
+    let code = alphabet.random_code(length);
+    let gif = render_captcha_gif(&code, fonts, width, height)?;
+    Ok((code, gif))
+}
+
+/// Caption of the captcha message for `pending`: the instruction plus countdown and
+/// counters.
+pub fn captcha_caption(user: &teloxide::types::User, pending: &PendingCaptcha) -> String {
+    let provider = pending_provider(pending);
     let name = escape_html(&user.first_name);
     let quote = CAPTCHA_QUOTES
         .choose(&mut rand::thread_rng())
@@ -85,33 +574,74 @@ pub fn captcha_caption(
         .unwrap_or("Tunjukkan kamu bukan bot.");
     let quote = escape_html(quote);
     let mention = format!("<a href=\"tg://user?id={}\">{}</a>", user.id.0, name);
+    let instruction = provider.instruction(pending.prompt.as_deref());
+    let input = provider
+        .input_echo(&pending.provider_data, &pending.code)
+        .map(|input| format!("\n⌨️ Input: <code>{}</code>", escape_html(&input)))
+        .unwrap_or_default();
+    let case_note = if pending.case_sensitive {
+        "\n🔠 Huruf besar/kecil <b>berpengaruh</b>."
+    } else if provider.accepts_text() {
+        "\n🔠 Huruf besar/kecil tidak berpengaruh."
+    } else {
+        ""
+    };
+    let PendingCaptcha {
+        remaining_secs,
+        attempts_left,
+        attempts_total,
+        refreshes_left,
+        refreshes_total,
+        ..
+    } = pending;
+    let refreshes = if *refreshes_total > 0 {
+        format!("\n🔄 Ganti gambar: <code>{refreshes_left}</code>/<code>{refreshes_total}</code>")
+    } else {
+        String::new()
+    };
     format!(
         "🖐🏼 Hi, {mention}\n\n\
 🙏🏼 <b>Please solve this captcha.</b>\n\
-💁🏻‍♂️ Pilih jawaban yang benar dari tombol yang tersedia.\n\n\
+{instruction}{case_note}{input}\n\n\
 ⏳ Dalam <code>{remaining_secs}</code> detik.\n\
-🎯 Kesempatan: <code>{attempts_left}</code>/<code>{attempts_total}</code>\n\n\
+🎯 Kesempatan: <code>{attempts_left}</code>/<code>{attempts_total}</code>{refreshes}\n\n\
 🗒 <i>{quote}</i>
 "
     )
 }
 
-pub fn generate_captcha_options(code: &str, count: usize) -> Vec<String> {
+/// Question text for quiz-poll challenges. Polls do not support HTML, so the user is
+/// addressed by (truncated) first name instead of a mention.
+pub fn captcha_poll_question(user: &teloxide::types::User, prompt: Option<&str>) -> String {
+    let name: String = sanitize_log_text(user.first_name.trim())
+        .chars()
+        .take(32)
+        .collect();
+    format!("🧮 {name}, berapa hasil dari {}?", prompt.unwrap_or("-"))
+}
+
+/// The code plus distractors of the same length drawn from `alphabet`. Distractors that
+/// could be mistaken for the code or for each other are rejected.
+pub fn generate_captcha_options(
+    code: &str,
+    count: usize,
+    alphabet: &CaptchaAlphabet,
+    case_sensitive: bool,
+) -> Vec<String> {
     let target = count.max(2);
     let mut options = Vec::with_capacity(target);
     options.push(code.to_string());
 
     let mut rng = rand::thread_rng();
-    while options.len() < target {
-        let candidate: String = (0..code.len())
-            .map(|_| {
-                let idx = rng.gen_range(0..CAPTCHA_SAFE_CHARS.len());
-                CAPTCHA_SAFE_CHARS[idx]
-            })
-            .collect();
+    let len = code.chars().count();
+    // Tiny alphabets with short codes may not have enough distinct codes; give up then.
+    let mut tries = 0;
+    while options.len() < target && tries < 1000 {
+        tries += 1;
+        let candidate = alphabet.random_code(len);
         if options
             .iter()
-            .all(|opt| !opt.eq_ignore_ascii_case(&candidate))
+            .all(|opt| !codes_confusable(opt, &candidate, case_sensitive))
         {
             options.push(candidate);
         }
@@ -121,23 +651,269 @@ pub fn generate_captcha_options(code: &str, count: usize) -> Vec<String> {
     options
 }
 
+fn option_to_display(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            'A' | 'a' => {
+                if matches!(chars.peek(), Some('B') | Some('b')) {
+                    chars.next();
+                    out.push('🆎');
+                } else {
+                    out.push_str("🅰️");
+                }
+            }
+            'B' | 'b' => out.push_str("🅱️"),
+            '0' => out.push_str("0️⃣"),
+            '1' => out.push_str("1️⃣"),
+            '2' => out.push_str("2️⃣"),
+            '3' => out.push_str("3️⃣"),
+            '4' => out.push_str("4️⃣"),
+            '5' => out.push_str("5️⃣"),
+            '6' => out.push_str("6️⃣"),
+            '7' => out.push_str("7️⃣"),
+            '8' => out.push_str("8️⃣"),
+            '9' => out.push_str("9️⃣"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// A pressed captcha button. Callback data is `captcha:<nonce>:<option index>` for answers,
+/// `captcha-press:<nonce>:<payload>` for provider-specific buttons such as keypad keys or
+/// grid tiles (see [`ChallengeProvider::on_press`]), and `captcha-audio:<nonce>` and
+/// `captcha-refresh:<nonce>` for the controls.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptchaCallback<'a> {
+    Answer { nonce: &'a str, index: usize },
+    Press { nonce: &'a str, payload: &'a str },
+    Audio { nonce: &'a str },
+    Refresh { nonce: &'a str },
+}
+
+impl<'a> CaptchaCallback<'a> {
+    pub fn parse(data: &'a str) -> Option<Self> {
+        let (kind, rest) = data.split_once(':')?;
+        match kind {
+            "captcha" => {
+                let (nonce, index) = rest.split_once(':')?;
+                Some(CaptchaCallback::Answer {
+                    nonce,
+                    index: index.parse().ok()?,
+                })
+            }
+            "captcha-press" => {
+                let (nonce, payload) = rest.split_once(':')?;
+                Some(CaptchaCallback::Press { nonce, payload })
+            }
+            "captcha-audio" => Some(CaptchaCallback::Audio { nonce: rest }),
+            "captcha-refresh" => Some(CaptchaCallback::Refresh { nonce: rest }),
+            _ => None,
+        }
+    }
+
+    pub fn nonce(&self) -> &'a str {
+        match *self {
+            CaptchaCallback::Answer { nonce, .. }
+            | CaptchaCallback::Press { nonce, .. }
+            | CaptchaCallback::Audio { nonce }
+            | CaptchaCallback::Refresh { nonce } => nonce,
+        }
+    }
+}
+
+pub fn new_captcha_nonce() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect()
+}
+
+pub enum CaptchaSession {
+    NoPending,
+    /// The button belongs to another user's captcha, or to an older captcha message.
+    Mismatch,
+    Current,
+}
+
+pub fn captcha_session(
+    state: &HashMap<CaptchaKey, PendingCaptcha>,
+    key: CaptchaKey,
+    nonce: &str,
+    message_id: MessageId,
+) -> CaptchaSession {
+    match state.get(&key) {
+        None => CaptchaSession::NoPending,
+        Some(pending) if pending.nonce == nonce && pending.captcha_message_id == message_id => {
+            CaptchaSession::Current
+        }
+        Some(_) => CaptchaSession::Mismatch,
+    }
+}
+
+/// Removes the session under `key` only while it is still the one with `nonce`. Private
+/// chat sessions share their key across groups, so a stale timer must not end a newer one.
+pub fn take_captcha_session(
+    state: &mut HashMap<CaptchaKey, PendingCaptcha>,
+    key: CaptchaKey,
+    nonce: &str,
+) -> Option<PendingCaptcha> {
+    if state.get(&key)?.nonce != nonce {
+        return None;
+    }
+    state.remove(&key)
+}
+
+/// Buttons shown below the answer options.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CaptchaControls {
+    pub audio: bool,
+    pub refresh: bool,
+}
+
+/// Answer buttons; callback data carries the option index, so labels may be rewritten
+/// freely (`digits_to_emoji`, `obfuscate`) without affecting which answer is picked.
+pub fn build_captcha_keyboard(
+    options: &[String],
+    digits_to_emoji: bool,
+    obfuscate: bool,
+    nonce: &str,
+    controls: CaptchaControls,
+) -> InlineKeyboardMarkup {
+    // Long answers (question bank) get a row each so Telegram does not truncate them.
+    let per_row = if options.iter().any(|opt| opt.chars().count() > 12) {
+        1
+    } else {
+        3
+    };
+    let mut rng = rand::thread_rng();
+    let mut rows: Vec<Vec<InlineKeyboardButton>> = options
+        .iter()
+        .enumerate()
+        .collect::<Vec<_>>()
+        .chunks(per_row)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|&(index, option)| {
+                    let display = if digits_to_emoji
+                        && option
+                            .chars()
+                            .any(|ch| ch.is_ascii_digit() || matches!(ch, 'A' | 'a' | 'B' | 'b'))
+                    {
+                        option_to_display(option)
+                    } else {
+                        option.to_string()
+                    };
+                    let display = if obfuscate {
+                        obfuscate_label(&display, &mut rng)
+                    } else {
+                        display
+                    };
+                    InlineKeyboardButton::callback(display, format!("captcha:{nonce}:{index}"))
+                })
+                .collect()
+        })
+        .collect();
+    push_control_row(&mut rows, nonce, controls);
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Callback data routed to [`ChallengeProvider::on_press`] with `payload`.
+pub fn press_data(nonce: &str, payload: &str) -> String {
+    format!("captcha-press:{nonce}:{payload}")
+}
+
+pub fn button_rows(
+    labels: &[String],
+    per_row: usize,
+    data: impl Fn(usize) -> String,
+) -> Vec<Vec<InlineKeyboardButton>> {
+    labels
+        .iter()
+        .enumerate()
+        .collect::<Vec<_>>()
+        .chunks(per_row)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .map(|&(index, label)| InlineKeyboardButton::callback(label, data(index)))
+                .collect()
+        })
+        .collect()
+}
+
+pub fn push_control_row(
+    rows: &mut Vec<Vec<InlineKeyboardButton>>,
+    nonce: &str,
+    controls: CaptchaControls,
+) {
+    let mut extra = Vec::new();
+    if controls.audio {
+        extra.push(InlineKeyboardButton::callback(
+            "🔊 Audio",
+            format!("captcha-audio:{nonce}"),
+        ));
+    }
+    if controls.refresh {
+        extra.push(InlineKeyboardButton::callback(
+            "🔄 Gambar baru",
+            format!("captcha-refresh:{nonce}"),
+        ));
+    }
+    if !extra.is_empty() {
+        rows.push(extra);
+    }
+}
+
+/// Who a new captcha is for and how long and how often they may try.
+pub struct SessionContext<'a> {
+    pub user: &'a teloxide::types::User,
+    pub chat_title: Option<String>,
+    pub chat_username: Option<String>,
+    pub attempts: usize,
+    pub timeout_secs: u64,
+}
+
 pub fn make_pending_captcha(
-    code: String,
+    provider: &str,
+    challenge: CaptchaChallenge,
     captcha_message_id: MessageId,
-    options: Vec<String>,
-    attempts_total: usize,
-    remaining_secs: u64,
-    user: &teloxide::types::User,
-    chat_title: Option<String>,
-    chat_username: Option<String>,
+    context: SessionContext,
 ) -> PendingCaptcha {
+    let SessionContext {
+        user,
+        chat_title,
+        chat_username,
+        attempts: attempts_total,
+        timeout_secs: remaining_secs,
+    } = context;
     PendingCaptcha {
-        code,
+        code: challenge.code,
+        provider: provider.to_string(),
+        provider_data: challenge.data,
         captcha_message_id,
-        options,
+        nonce: String::new(),
+        options: challenge.options,
+        prompt: challenge.prompt,
+        poll_id: None,
+        audio_message_id: None,
+        refreshes_left: 0,
+        refreshes_total: 0,
         attempts_left: attempts_total,
         attempts_total,
         remaining_secs,
+        expires_at: chrono::Utc::now().timestamp() + remaining_secs as i64,
+        sent_at_ms: chrono::Utc::now().timestamp_millis(),
+        last_answer_at_ms: None,
+        case_sensitive: false,
+        join_request_chat: None,
+        private_origin: None,
+        trust_check: false,
+        strict: false,
         user_display: format_user_display(user),
         user_name: format_user_name(user),
         user_username: user.username.as_deref().map(|raw| raw.trim().to_string()),
@@ -146,6 +922,55 @@ pub fn make_pending_captcha(
     }
 }
 
+/// User the tests start captchas for.
+#[cfg(test)]
+pub(crate) fn test_user() -> teloxide::types::User {
+    teloxide::types::User {
+        id: UserId(2),
+        is_bot: false,
+        first_name: "User".to_string(),
+        last_name: None,
+        username: None,
+        language_code: None,
+        is_premium: false,
+        added_to_attachment_menu: false,
+    }
+}
+
+/// A fresh session for `code` from `provider`, sent to [`test_user`] as message 10.
+#[cfg(test)]
+pub(crate) fn test_pending(provider: &str, code: &str) -> PendingCaptcha {
+    let challenge = CaptchaChallenge {
+        code: code.to_string(),
+        media: None,
+        options: vec![code.to_string()],
+        prompt: None,
+        data: serde_json::Value::Null,
+    };
+    make_pending_captcha(
+        provider,
+        challenge,
+        MessageId(10),
+        SessionContext {
+            user: &test_user(),
+            chat_title: None,
+            chat_username: None,
+            attempts: 3,
+            timeout_secs: 120,
+        },
+    )
+}
+
+pub fn find_captcha_by_poll(
+    state: &HashMap<CaptchaKey, PendingCaptcha>,
+    poll_id: &str,
+) -> Option<CaptchaKey> {
+    state
+        .iter()
+        .find(|(_, pending)| pending.poll_id.as_deref() == Some(poll_id))
+        .map(|(key, _)| *key)
+}
+
 pub fn check_captcha_answer(
     state: &mut HashMap<CaptchaKey, PendingCaptcha>,
     key: CaptchaKey,
@@ -154,14 +979,37 @@ pub fn check_captcha_answer(
     let Some(pending) = state.get(&key).cloned() else {
         return CaptchaCheck::NoPending;
     };
-    if text.eq_ignore_ascii_case(&pending.code) {
+    if pending_provider(&pending).check_answer(&pending, text) {
         state.remove(&key);
-        CaptchaCheck::Verified(pending)
+        CaptchaCheck::Verified(Box::new(pending))
     } else {
         CaptchaCheck::Wrong
     }
 }
 
+/// Records that an answer arrived at `answered_at_ms` and checks it. Answers quicker than
+/// `min_solve_ms` after the challenge was shown are [`CaptchaCheck::TooFast`], right or
+/// not; 0 turns that off.
+pub fn check_timed_captcha_answer(
+    state: &mut HashMap<CaptchaKey, PendingCaptcha>,
+    key: CaptchaKey,
+    text: &str,
+    answered_at_ms: i64,
+    min_solve_ms: u64,
+) -> CaptchaCheck {
+    let Some(pending) = state.get_mut(&key) else {
+        return CaptchaCheck::NoPending;
+    };
+    pending.last_answer_at_ms = Some(answered_at_ms);
+    if pending
+        .solve_ms()
+        .is_some_and(|solve_ms| solve_ms < min_solve_ms as i64)
+    {
+        return CaptchaCheck::TooFast;
+    }
+    check_captcha_answer(state, key, text)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -174,11 +1022,27 @@ mod tests {
             key,
             PendingCaptcha {
                 code: "AbC".to_string(),
+                provider: "image".to_string(),
+                provider_data: serde_json::Value::Null,
                 captcha_message_id: MessageId(10),
+                nonce: "n0nce".to_string(),
                 options: vec!["AbC".to_string(), "ZZZ".to_string()],
+                prompt: None,
+                poll_id: None,
+                audio_message_id: None,
+                refreshes_left: 0,
+                refreshes_total: 0,
                 attempts_left: 3,
                 attempts_total: 3,
                 remaining_secs: 120,
+                expires_at: 0,
+                sent_at_ms: 1_000,
+                last_answer_at_ms: None,
+                case_sensitive: false,
+                join_request_chat: None,
+                private_origin: None,
+                trust_check: false,
+                strict: false,
                 user_display: "User @user".to_string(),
                 user_name: "User".to_string(),
                 user_username: Some("user".to_string()),
@@ -194,4 +1058,234 @@ mod tests {
         assert!(matches!(verified, CaptchaCheck::Verified(_)));
         assert!(!state.contains_key(&key));
     }
+
+    #[test]
+    fn check_captcha_answer_respects_case_sensitivity() {
+        let key = (ChatId(1), UserId(2));
+        let mut state = HashMap::from([(key, test_pending("image", "AbC"))]);
+        let verified = check_captcha_answer(&mut state, key, "aBc");
+        assert!(matches!(verified, CaptchaCheck::Verified(_)));
+
+        let mut strict = test_pending("image", "AbC");
+        strict.case_sensitive = true;
+        let mut state = HashMap::from([(key, strict.clone())]);
+        let wrong = check_captcha_answer(&mut state, key, "aBc");
+        assert!(matches!(wrong, CaptchaCheck::Wrong));
+        let verified = check_captcha_answer(&mut state, key, "AbC");
+        assert!(matches!(verified, CaptchaCheck::Verified(_)));
+
+        // Typed answers may be spaced out, but keep their case when it matters.
+        assert!(!TypedChallenge.check_answer(&strict, "aBc"));
+        assert!(TypedChallenge.check_answer(&strict, "A b C"));
+    }
+
+    #[test]
+    fn timed_answers_below_min_solve_time_are_too_fast() {
+        let key = (ChatId(1), UserId(2));
+        let mut pending = test_pending("image", "AbC");
+        pending.sent_at_ms = 1_000;
+        let mut state = HashMap::from([(key, pending)]);
+
+        let too_fast = check_timed_captcha_answer(&mut state, key, "aBc", 1_300, 1_500);
+        assert!(matches!(too_fast, CaptchaCheck::TooFast));
+        assert_eq!(state[&key].solve_ms(), Some(300));
+
+        let verified = check_timed_captcha_answer(&mut state, key, "aBc", 4_500, 1_500);
+        let CaptchaCheck::Verified(pending) = verified else {
+            panic!("expected verified");
+        };
+        assert_eq!(pending.solve_ms(), Some(3_500));
+    }
+
+    #[test]
+    fn generate_captcha_options_stay_in_alphabet_without_look_alikes() {
+        let mut warnings = Vec::new();
+        let digits = CaptchaAlphabet::parse("digits", false, &mut warnings).unwrap();
+        let options = generate_captcha_options("4821", 12, &digits, false);
+        assert_eq!(options.len(), 12);
+        assert!(options.contains(&"4821".to_string()));
+        assert!(
+            options
+                .iter()
+                .all(|opt| opt.len() == 4 && opt.chars().all(|ch| ch.is_ascii_digit()))
+        );
+
+        let mixed = CaptchaAlphabet::parse("mixed", true, &mut warnings).unwrap();
+        let options = generate_captcha_options("aB3", 12, &mixed, true);
+        for (i, a) in options.iter().enumerate() {
+            for b in &options[i + 1..] {
+                assert!(!codes_confusable(a, b, true), "{a} vs {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn build_captcha_keyboard_adds_control_row() {
+        let options = vec!["AB".to_string(), "CD".to_string()];
+        let plain =
+            build_captcha_keyboard(&options, false, false, "n0nce", CaptchaControls::default());
+        assert_eq!(plain.inline_keyboard.len(), 1);
+
+        let controls = CaptchaControls {
+            audio: true,
+            refresh: true,
+        };
+        let keyboard = build_captcha_keyboard(&options, false, false, "n0nce", controls);
+        assert_eq!(keyboard.inline_keyboard.len(), 2);
+        assert_eq!(keyboard.inline_keyboard[1].len(), 2);
+        assert_eq!(keyboard.inline_keyboard[1][1].text, "🔄 Gambar baru");
+    }
+
+    fn data(button: &InlineKeyboardButton) -> String {
+        match &button.kind {
+            teloxide::types::InlineKeyboardButtonKind::CallbackData(data) => data.clone(),
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn captcha_callback_round_trips_through_keyboard() {
+        let options = vec!["AB".to_string(), "CD".to_string()];
+        let controls = CaptchaControls {
+            audio: true,
+            refresh: false,
+        };
+        let keyboard = build_captcha_keyboard(&options, false, true, "n0nce", controls);
+        // Obfuscated labels still decode to the option they stand for.
+        assert_ne!(keyboard.inline_keyboard[0][1].text, "CD");
+        assert_eq!(
+            CaptchaCallback::parse(&data(&keyboard.inline_keyboard[0][1])),
+            Some(CaptchaCallback::Answer {
+                nonce: "n0nce",
+                index: 1
+            })
+        );
+        assert_eq!(
+            CaptchaCallback::parse(&data(&keyboard.inline_keyboard[1][0])),
+            Some(CaptchaCallback::Audio { nonce: "n0nce" })
+        );
+        assert_eq!(
+            CaptchaCallback::parse(&press_data("n0nce", "key1")),
+            Some(CaptchaCallback::Press {
+                nonce: "n0nce",
+                payload: "key1"
+            })
+        );
+        // Buttons from before nonces were introduced.
+        assert_eq!(CaptchaCallback::parse("captcha:AB34XY"), None);
+    }
+
+    #[test]
+    fn obfuscated_labels_vary_between_renders() {
+        let options = vec!["K7P3".to_string(), "AB".to_string()];
+        let render =
+            || build_captcha_keyboard(&options, false, true, "n0nce", CaptchaControls::default());
+        let first = render();
+        let mut differed = false;
+        for _ in 0..50 {
+            let keyboard = render();
+            differed |= keyboard.inline_keyboard[0][0].text != first.inline_keyboard[0][0].text;
+            for (index, button) in keyboard.inline_keyboard[0].iter().enumerate() {
+                assert_eq!(
+                    CaptchaCallback::parse(&data(button)),
+                    Some(CaptchaCallback::Answer {
+                        nonce: "n0nce",
+                        index
+                    })
+                );
+            }
+        }
+        assert!(differed);
+    }
+
+    #[test]
+    fn captcha_session_requires_matching_nonce_and_message() {
+        let mut state: HashMap<CaptchaKey, PendingCaptcha> = HashMap::new();
+        let key = (ChatId(1), UserId(2));
+        let mut pending = test_pending("image", "AB");
+        pending.nonce = "n0nce".to_string();
+        state.insert(key, pending);
+        assert!(matches!(
+            captcha_session(&state, key, "n0nce", MessageId(10)),
+            CaptchaSession::Current
+        ));
+        assert!(matches!(
+            captcha_session(&state, key, "other", MessageId(10)),
+            CaptchaSession::Mismatch
+        ));
+        assert!(matches!(
+            captcha_session(&state, key, "n0nce", MessageId(11)),
+            CaptchaSession::Mismatch
+        ));
+        assert!(matches!(
+            captcha_session(&state, (ChatId(1), UserId(3)), "n0nce", MessageId(10)),
+            CaptchaSession::NoPending
+        ));
+
+        // A timer from an earlier session under the same key leaves the current one alone.
+        assert!(take_captcha_session(&mut state, key, "other").is_none());
+        assert!(take_captcha_session(&mut state, key, "n0nce").is_some());
+        assert!(state.is_empty());
+    }
+
+    #[test]
+    fn challenge_provider_lookup_by_name_and_alias() {
+        assert_eq!(challenge_provider("image").unwrap().name(), "image");
+        assert_eq!(challenge_provider("GIF").unwrap().name(), "animated");
+        assert_eq!(challenge_provider(" Quiz ").unwrap().name(), "poll");
+        assert_eq!(challenge_provider("arithmetic").unwrap().name(), "math");
+        assert!(challenge_provider("puzzle").is_none());
+    }
+
+    #[test]
+    fn typed_joiners_can_still_send_text() {
+        let typed = challenge_provider("typed").unwrap();
+        assert!(
+            typed
+                .restricted_permissions()
+                .contains(ChatPermissions::SEND_MESSAGES)
+        );
+        let image = challenge_provider("image").unwrap();
+        assert_eq!(image.restricted_permissions(), ChatPermissions::empty());
+    }
+
+    #[test]
+    fn pending_captcha_reads_legacy_mode_field() {
+        let raw = r#"{"code":"17","mode":"poll","captcha_message_id":{"message_id":10},"options":["17"],
+            "attempts_left":3,"attempts_total":3,"remaining_secs":120,"expires_at":0,
+            "user_display":"User","user_name":"User","user_username":null,
+            "chat_title":null,"chat_username":null}"#;
+        let pending: PendingCaptcha = serde_json::from_str(raw).unwrap();
+        assert_eq!(pending.provider, "poll");
+        assert_eq!(
+            pending_provider(&pending).message_kind(),
+            CaptchaMessageKind::Poll
+        );
+        // Sessions from before join requests guard the chat they were sent in.
+        assert_eq!(pending.guarded_chat(ChatId(-100)), ChatId(-100));
+
+        let mut private = pending.clone();
+        private.join_request_chat = Some(ChatId(-200));
+        let json = serde_json::to_string(&private).unwrap();
+        let restored: PendingCaptcha = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.guarded_chat(ChatId(7)), ChatId(-200));
+
+        let mut moved = pending.clone();
+        moved.private_origin = Some(PrivateOrigin {
+            chat_id: ChatId(-300),
+            stub_message_id: MessageId(11),
+        });
+        assert_eq!(moved.guarded_chat(ChatId(7)), ChatId(-300));
+    }
+
+    #[test]
+    fn find_captcha_by_poll_matches_poll_id() {
+        let mut state: HashMap<CaptchaKey, PendingCaptcha> = HashMap::new();
+        let key = (ChatId(1), UserId(2));
+        let mut pending = test_pending("poll", "17");
+        pending.poll_id = Some("poll-1".to_string());
+        state.insert(key, pending);
+        assert_eq!(find_captcha_by_poll(&state, "poll-1"), Some(key));
+        assert_eq!(find_captcha_by_poll(&state, "poll-2"), None);
+    }
 }
@@ -0,0 +1,196 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use teloxide::types::ChatId;
+
+/// Highest difficulty level; level 0 is the configured baseline.
+pub const MAX_ADAPTIVE_LEVEL: u8 = 3;
+
+/// Difficulty actually used for a captcha in a chat.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CaptchaDifficulty {
+    pub level: u8,
+    pub len: usize,
+    pub option_count: usize,
+    pub timeout_secs: u64,
+}
+
+impl CaptchaDifficulty {
+    /// Each level adds two characters and two answer buttons and takes 20% off the timeout.
+    pub fn at_level(level: u8, len: usize, option_count: usize, timeout_secs: u64) -> Self {
+        let level = level.min(MAX_ADAPTIVE_LEVEL);
+        let step = level as usize;
+        Self {
+            level,
+            len: (len + 2 * step).min(12).max(len),
+            option_count: (option_count + 2 * step).min(12).max(option_count),
+            timeout_secs: (timeout_secs * (10 - 2 * level as u64) / 10)
+                .max(30)
+                .min(timeout_secs),
+        }
+    }
+}
+
+/// A level change worth logging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LevelChange {
+    pub from: u8,
+    pub to: u8,
+    pub joins: usize,
+    pub failures: usize,
+}
+
+#[derive(Debug, Default)]
+struct ChatActivity {
+    joins: VecDeque<i64>,
+    failures: VecDeque<i64>,
+    level: u8,
+    changed_at: i64,
+}
+
+/// Tracks joins and failed captchas per chat over a sliding window. Every `join_threshold`
+/// joins (or half as many failures) in the window raises the level by one; once traffic
+/// drops, the level relaxes one step per half window.
+#[derive(Debug)]
+pub struct AdaptiveDifficulty {
+    window_secs: i64,
+    join_threshold: usize,
+    chats: Mutex<HashMap<ChatId, ChatActivity>>,
+}
+
+impl AdaptiveDifficulty {
+    pub fn new(window_secs: u64, join_threshold: usize) -> Self {
+        Self {
+            window_secs: window_secs as i64,
+            join_threshold: join_threshold.max(2),
+            chats: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn record_join(&self, chat_id: ChatId, now: i64) -> Option<LevelChange> {
+        self.record(chat_id, now, |activity| activity.joins.push_back(now))
+    }
+
+    pub fn record_failure(&self, chat_id: ChatId, now: i64) -> Option<LevelChange> {
+        self.record(chat_id, now, |activity| activity.failures.push_back(now))
+    }
+
+    /// Current level of a chat, applying any relaxation due since the last event.
+    pub fn level(&self, chat_id: ChatId, now: i64) -> u8 {
+        let mut chats = self.chats.lock().unwrap_or_else(|err| err.into_inner());
+        let Some(activity) = chats.get_mut(&chat_id) else {
+            return 0;
+        };
+        self.update(activity, now);
+        let level = activity.level;
+        if level == 0 && activity.joins.is_empty() && activity.failures.is_empty() {
+            chats.remove(&chat_id);
+        }
+        level
+    }
+
+    /// Chats currently above the baseline, highest level first.
+    pub fn raised_levels(&self, now: i64) -> Vec<(ChatId, u8)> {
+        let mut chats = self.chats.lock().unwrap_or_else(|err| err.into_inner());
+        let mut raised: Vec<(ChatId, u8)> = chats
+            .iter_mut()
+            .filter_map(|(chat_id, activity)| {
+                self.update(activity, now);
+                (activity.level > 0).then_some((*chat_id, activity.level))
+            })
+            .collect();
+        raised.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.0.cmp(&b.0.0)));
+        raised
+    }
+
+    fn record(
+        &self,
+        chat_id: ChatId,
+        now: i64,
+        push: impl FnOnce(&mut ChatActivity),
+    ) -> Option<LevelChange> {
+        let mut chats = self.chats.lock().unwrap_or_else(|err| err.into_inner());
+        let activity = chats.entry(chat_id).or_default();
+        push(activity);
+        let from = activity.level;
+        self.update(activity, now);
+        (activity.level != from).then_some(LevelChange {
+            from,
+            to: activity.level,
+            joins: activity.joins.len(),
+            failures: activity.failures.len(),
+        })
+    }
+
+    fn update(&self, activity: &mut ChatActivity, now: i64) {
+        let cutoff = now - self.window_secs;
+        while activity.joins.front().is_some_and(|&at| at <= cutoff) {
+            activity.joins.pop_front();
+        }
+        while activity.failures.front().is_some_and(|&at| at <= cutoff) {
+            activity.failures.pop_front();
+        }
+
+        let by_joins = activity.joins.len() / self.join_threshold;
+        let by_failures = activity.failures.len() * 2 / self.join_threshold;
+        let target = by_joins.max(by_failures).min(MAX_ADAPTIVE_LEVEL as usize) as u8;
+        if target >= activity.level {
+            // Still under load: hold the level and restart the relax clock.
+            activity.level = target;
+            activity.changed_at = now;
+        } else {
+            let step_secs = (self.window_secs / 2).max(1);
+            let steps = ((now - activity.changed_at) / step_secs).clamp(0, 255) as u8;
+            if steps > 0 {
+                activity.level = activity.level.saturating_sub(steps).max(target);
+                activity.changed_at = now;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_rises_with_joins_and_relaxes_stepwise() {
+        let adaptive = AdaptiveDifficulty::new(600, 5);
+        let chat = ChatId(-100);
+        let mut change = None;
+        for i in 0..10 {
+            change = adaptive.record_join(chat, 1_000 + i).or(change);
+        }
+        assert_eq!(change.map(|c| c.to), Some(2));
+        assert_eq!(adaptive.level(chat, 1_450), 2);
+        // Joins have left the window, but the level only drops one step per half window.
+        assert_eq!(adaptive.level(chat, 1_620), 2);
+        assert_eq!(adaptive.level(chat, 1_760), 1);
+        assert_eq!(adaptive.level(chat, 2_070), 0);
+        assert!(adaptive.raised_levels(2_070).is_empty());
+    }
+
+    #[test]
+    fn failures_count_double() {
+        let adaptive = AdaptiveDifficulty::new(600, 4);
+        let chat = ChatId(-100);
+        adaptive.record_failure(chat, 10);
+        let change = adaptive.record_failure(chat, 11).unwrap();
+        assert_eq!((change.from, change.to), (0, 1));
+        assert_eq!(adaptive.raised_levels(12), vec![(chat, 1)]);
+    }
+
+    #[test]
+    fn difficulty_scales_from_baseline() {
+        let base = CaptchaDifficulty::at_level(0, 6, 6, 120);
+        assert_eq!(
+            (base.len, base.option_count, base.timeout_secs),
+            (6, 6, 120)
+        );
+        let raised = CaptchaDifficulty::at_level(3, 6, 6, 120);
+        assert_eq!(
+            (raised.len, raised.option_count, raised.timeout_secs),
+            (12, 12, 48)
+        );
+    }
+}
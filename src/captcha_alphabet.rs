@@ -0,0 +1,160 @@
+use rand::Rng;
+
+/// Default alphabet: upper-case letters and digits without look-alikes (0/O, 1/I/L, 5/S).
+pub const CAPTCHA_SAFE_CHARS: &[char] = &[
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'J', 'K', 'M', 'N', 'P', 'Q', 'R', 'T', 'U', 'V', 'W',
+    'X', 'Y', 'Z', '2', '3', '4', '6', '7', '8', '9',
+];
+
+/// Lower-case letters whose shape differs from their upper-case form; only used when
+/// answers are case-sensitive.
+const MIXED_EXTRA_CHARS: &[char] = &['a', 'd', 'e', 'f', 'h', 'n', 'r', 't', 'y'];
+
+/// Characters that are easy to mistake for each other in a noisy image or on a button.
+/// Two codes whose characters fall in the same groups are treated as duplicates.
+const CONFUSABLE_GROUPS: &[&str] = &[
+    "0Oo", "1IiLl", "5Ss", "6b", "9gq", "Cc", "Jj", "Kk", "Mm", "Pp", "Uu", "Vv", "Ww", "Xx", "Zz",
+];
+
+/// Characters captcha codes are drawn from, parsed from a preset name or a literal list.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CaptchaAlphabet {
+    chars: Vec<char>,
+}
+
+impl Default for CaptchaAlphabet {
+    fn default() -> Self {
+        Self {
+            chars: CAPTCHA_SAFE_CHARS.to_vec(),
+        }
+    }
+}
+
+impl CaptchaAlphabet {
+    /// Parses `safe`, `digits`, `mixed` (safe plus distinct lower-case letters) or a literal
+    /// list of ASCII letters and digits. Without `case_sensitive`, letters are folded to
+    /// upper case. Look-alikes of an earlier character are dropped and reported in
+    /// `warnings`.
+    pub fn parse(
+        spec: &str,
+        case_sensitive: bool,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, String> {
+        let spec = spec.trim();
+        let raw: Vec<char> = match spec.to_ascii_lowercase().as_str() {
+            "safe" | "default" => CAPTCHA_SAFE_CHARS.to_vec(),
+            "digits" | "numeric" => ('0'..='9').collect(),
+            "mixed" => CAPTCHA_SAFE_CHARS
+                .iter()
+                .chain(MIXED_EXTRA_CHARS)
+                .copied()
+                .collect(),
+            _ => {
+                if let Some(bad) = spec.chars().find(|ch| !ch.is_ascii_alphanumeric()) {
+                    return Err(format!("unsupported character '{bad}'"));
+                }
+                spec.chars().collect()
+            }
+        };
+
+        let mut chars: Vec<char> = Vec::with_capacity(raw.len());
+        let mut dropped = String::new();
+        for ch in raw {
+            let ch = if case_sensitive {
+                ch
+            } else {
+                ch.to_ascii_uppercase()
+            };
+            if chars.contains(&ch) {
+                continue;
+            }
+            if chars
+                .iter()
+                .any(|&kept| confusable(kept, ch, case_sensitive))
+            {
+                dropped.push(ch);
+                continue;
+            }
+            chars.push(ch);
+        }
+        if !dropped.is_empty() {
+            warnings.push(format!(
+                "captcha alphabet '{spec}': dropped look-alike characters: {dropped}"
+            ));
+        }
+        if chars.len() < 2 {
+            return Err("needs at least 2 distinct characters".to_string());
+        }
+        Ok(Self { chars })
+    }
+
+    pub fn chars(&self) -> &[char] {
+        &self.chars
+    }
+
+    pub fn has_lowercase(&self) -> bool {
+        self.chars.iter().any(char::is_ascii_lowercase)
+    }
+
+    pub fn random_code(&self, length: usize) -> String {
+        let mut rng = rand::thread_rng();
+        (0..length)
+            .map(|_| self.chars[rng.gen_range(0..self.chars.len())])
+            .collect()
+    }
+}
+
+fn confusable_group(ch: char) -> Option<usize> {
+    CONFUSABLE_GROUPS
+        .iter()
+        .position(|group| group.contains(ch))
+}
+
+fn confusable(a: char, b: char, case_sensitive: bool) -> bool {
+    if a == b || (!case_sensitive && a.eq_ignore_ascii_case(&b)) {
+        return true;
+    }
+    confusable_group(a).is_some_and(|group| confusable_group(b) == Some(group))
+}
+
+/// Whether two codes could be mistaken for each other, character by character.
+pub fn codes_confusable(a: &str, b: &str, case_sensitive: bool) -> bool {
+    a.chars().count() == b.chars().count()
+        && a.chars()
+            .zip(b.chars())
+            .all(|(x, y)| confusable(x, y, case_sensitive))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_presets_and_literal_lists() {
+        let mut warnings = Vec::new();
+        let digits = CaptchaAlphabet::parse("digits", false, &mut warnings).unwrap();
+        assert_eq!(digits.chars().len(), 10);
+
+        // Folded to upper case, then the look-alikes of 0 and 1 are dropped.
+        let literal = CaptchaAlphabet::parse("ab01oI", false, &mut warnings).unwrap();
+        assert_eq!(literal.chars(), &['A', 'B', '0', '1']);
+        assert_eq!(warnings.len(), 1);
+
+        let mixed = CaptchaAlphabet::parse("mixed", true, &mut warnings).unwrap();
+        assert!(mixed.has_lowercase());
+        let folded = CaptchaAlphabet::parse("mixed", false, &mut warnings).unwrap();
+        assert_eq!(folded, CaptchaAlphabet::default());
+
+        assert!(CaptchaAlphabet::parse("AB-12", false, &mut warnings).is_err());
+        assert!(CaptchaAlphabet::parse("aA", false, &mut warnings).is_err());
+    }
+
+    #[test]
+    fn codes_confusable_respects_case_mode() {
+        assert!(codes_confusable("AB0", "ab0", false));
+        assert!(!codes_confusable("AB0", "ab0", true));
+        assert!(codes_confusable("CX0", "cxO", true));
+        assert!(!codes_confusable("B8", "8B", false));
+        assert!(!codes_confusable("AB", "ABC", false));
+    }
+}
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use teloxide::types::{ChatId, UserId};
+
+/// How long an approved join request covers the join that follows it.
+const APPROVAL_TTL_SECS: i64 = 120;
+
+/// Join requests the bot approved recently. Telegram reports the resulting join like any
+/// other (`chat_member` update and service message), so without this the applicant who just
+/// passed the private captcha would be restricted and challenged again in the group.
+#[derive(Debug, Default)]
+pub struct JoinApprovals {
+    approved: Mutex<HashMap<(ChatId, UserId), i64>>,
+}
+
+impl JoinApprovals {
+    pub fn record(&self, chat_id: ChatId, user_id: UserId, now_ts: i64) {
+        let mut approved = self.approved.lock().unwrap_or_else(|err| err.into_inner());
+        approved.retain(|_, at| now_ts - *at < APPROVAL_TTL_SECS);
+        approved.insert((chat_id, user_id), now_ts);
+    }
+
+    /// Whether `user_id` joining `chat_id` is the join of a request approved moments ago.
+    /// Entries are not consumed: the join arrives both as an update and as a service message.
+    pub fn is_approved(&self, chat_id: ChatId, user_id: UserId, now_ts: i64) -> bool {
+        let approved = self.approved.lock().unwrap_or_else(|err| err.into_inner());
+        approved
+            .get(&(chat_id, user_id))
+            .is_some_and(|at| now_ts - *at < APPROVAL_TTL_SECS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn approvals_cover_the_following_join_only_briefly() {
+        let approvals = JoinApprovals::default();
+        approvals.record(ChatId(-1), UserId(7), 1_000);
+        assert!(approvals.is_approved(ChatId(-1), UserId(7), 1_010));
+        assert!(!approvals.is_approved(ChatId(-2), UserId(7), 1_010));
+        assert!(!approvals.is_approved(ChatId(-1), UserId(7), 1_000 + APPROVAL_TTL_SECS));
+    }
+}
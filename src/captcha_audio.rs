@@ -0,0 +1,384 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use audiopus::coder::Encoder;
+use audiopus::{Application, Channels, SampleRate};
+use ogg::writing::{PacketWriteEndInfo, PacketWriter};
+use rand::{Rng, seq::SliceRandom};
+
+use crate::utils::sanitize_log_text;
+
+/// Sample rate of the rendered audio; samples are resampled to it on load.
+const AUDIO_SAMPLE_RATE: u32 = 16_000;
+
+/// 20 ms Opus frames.
+const OPUS_FRAME_LEN: usize = AUDIO_SAMPLE_RATE as usize / 50;
+
+/// Ogg granule positions of Opus streams always count 48 kHz samples.
+const OPUS_GRANULE_SCALE: u64 = 48_000 / AUDIO_SAMPLE_RATE as u64;
+
+/// Recordings of `0`-`9` and `a`-`z` shipped in `assets/audio/<lang>/`.
+macro_rules! bundled_samples {
+    ($lang:literal) => {
+        bundled_samples!($lang:
+            '0' '1' '2' '3' '4' '5' '6' '7' '8' '9'
+            'a' 'b' 'c' 'd' 'e' 'f' 'g' 'h' 'i' 'j' 'k' 'l' 'm'
+            'n' 'o' 'p' 'q' 'r' 's' 't' 'u' 'v' 'w' 'x' 'y' 'z')
+    };
+    ($lang:literal: $($ch:literal)*) => {
+        &[$(($ch, include_bytes!(concat!("../assets/audio/", $lang, "/", $ch, ".wav")) as &[u8])),*]
+    };
+}
+
+const BUNDLED_ID: &[(char, &[u8])] = bundled_samples!("id");
+const BUNDLED_EN: &[(char, &[u8])] = bundled_samples!("en");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AudioLang {
+    Indonesian,
+    English,
+}
+
+impl AudioLang {
+    const ALL: [AudioLang; 2] = [AudioLang::Indonesian, AudioLang::English];
+
+    /// Sub-directory of `CAPTCHA_AUDIO_SAMPLES_DIR` holding this language's samples.
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            AudioLang::Indonesian => "id",
+            AudioLang::English => "en",
+        }
+    }
+
+    fn bundled(self) -> &'static [(char, &'static [u8])] {
+        match self {
+            AudioLang::Indonesian => BUNDLED_ID,
+            AudioLang::English => BUNDLED_EN,
+        }
+    }
+
+    /// English for users whose Telegram client is set to English, Indonesian otherwise.
+    pub fn for_user(user: &teloxide::types::User) -> Self {
+        match user.language_code.as_deref() {
+            Some(code) if code.to_ascii_lowercase().starts_with("en") => AudioLang::English,
+            _ => AudioLang::Indonesian,
+        }
+    }
+}
+
+/// Per-character voice samples, one set per language. A language is only kept when every
+/// character of the configured alphabets has a sample.
+#[derive(Clone, Default)]
+pub struct CaptchaVoice {
+    langs: HashMap<AudioLang, HashMap<char, Vec<f32>>>,
+}
+
+impl fmt::Debug for CaptchaVoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut langs: Vec<&str> = self.langs.keys().map(|lang| lang.dir_name()).collect();
+        langs.sort_unstable();
+        f.debug_struct("CaptchaVoice")
+            .field("langs", &langs)
+            .finish()
+    }
+}
+
+impl CaptchaVoice {
+    /// Loads `<dir>/<lang>/<char>.wav` (lowercase character, mono or stereo PCM) for each of
+    /// `chars`. Incomplete languages are reported through `warnings` and skipped.
+    pub fn load(dir: &Path, chars: &[char], warnings: &mut Vec<String>) -> Self {
+        let mut langs = HashMap::new();
+        for lang in AudioLang::ALL {
+            let lang_dir = dir.join(lang.dir_name());
+            let mut samples = HashMap::new();
+            let mut missing = Vec::new();
+            for &ch in chars {
+                let path = lang_dir.join(format!("{}.wav", ch.to_ascii_lowercase()));
+                match hound::WavReader::open(&path)
+                    .map_err(Into::into)
+                    .and_then(decode_sample)
+                {
+                    Ok(sample) => {
+                        samples.insert(ch, sample);
+                    }
+                    Err(_) => missing.push(ch),
+                }
+            }
+            if missing.is_empty() {
+                langs.insert(lang, samples);
+            } else if missing.len() < chars.len() {
+                let missing: String = missing.into_iter().collect();
+                warnings.push(format!(
+                    "CAPTCHA_AUDIO_SAMPLES_DIR '{}' is missing samples for: {}; language disabled",
+                    sanitize_log_text(&lang_dir.display().to_string()),
+                    missing
+                ));
+            }
+        }
+        if langs.is_empty() {
+            warnings.push(format!(
+                "CAPTCHA_AUDIO_SAMPLES_DIR has no complete sample set ('{}'); audio captcha disabled",
+                sanitize_log_text(&dir.display().to_string())
+            ));
+        }
+        Self { langs }
+    }
+
+    /// The samples bundled with the bot, used without a sample directory. They cover
+    /// letters and digits; other characters leave audio disabled.
+    pub fn builtin(chars: &[char], warnings: &mut Vec<String>) -> Self {
+        let bundled = |lang: AudioLang, ch: char| {
+            let (_, wav) = lang
+                .bundled()
+                .iter()
+                .find(|(name, _)| *name == ch.to_ascii_lowercase())?;
+            decode_sample(hound::WavReader::new(Cursor::new(*wav)).ok()?).ok()
+        };
+        let mut langs = HashMap::new();
+        for lang in AudioLang::ALL {
+            let mut samples = HashMap::new();
+            let mut missing = String::new();
+            for &ch in chars {
+                match bundled(lang, ch) {
+                    Some(sample) => {
+                        samples.insert(ch, sample);
+                    }
+                    None => missing.push(ch),
+                }
+            }
+            if !missing.is_empty() {
+                warnings.push(format!(
+                    "built-in audio captcha samples cannot read out: {}; set CAPTCHA_AUDIO_SAMPLES_DIR, audio captcha disabled",
+                    sanitize_log_text(&missing)
+                ));
+                return Self::default();
+            }
+            langs.insert(lang, samples);
+        }
+        Self { langs }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.langs.is_empty()
+    }
+
+    fn samples(&self, lang: AudioLang) -> Option<&HashMap<char, Vec<f32>>> {
+        self.langs
+            .get(&lang)
+            .or_else(|| AudioLang::ALL.iter().find_map(|lang| self.langs.get(lang)))
+    }
+}
+
+/// Decodes a WAV file as mono `f32` at [`AUDIO_SAMPLE_RATE`].
+fn decode_sample<R: Read>(
+    mut reader: hound::WavReader<R>,
+) -> Result<Vec<f32>, Box<dyn Error + Send + Sync>> {
+    let spec = reader.spec();
+    let raw: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|sample| sample.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let channels = spec.channels.max(1) as usize;
+    let mono: Vec<f32> = raw
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+    Ok(resample(&mono, spec.sample_rate, AUDIO_SAMPLE_RATE))
+}
+
+/// Linear-interpolation resampler; good enough for speech at 16 kHz.
+fn resample(input: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || input.is_empty() {
+        return input.to_vec();
+    }
+    let ratio = from as f64 / to as f64;
+    let len = (input.len() as f64 / ratio) as usize;
+    (0..len)
+        .map(|i| {
+            let pos = i as f64 * ratio;
+            let idx = pos as usize;
+            let frac = (pos - idx as f64) as f32;
+            let a = input[idx.min(input.len() - 1)];
+            let b = input[(idx + 1).min(input.len() - 1)];
+            a + (b - a) * frac
+        })
+        .collect()
+}
+
+/// Speaks `code` character by character over background noise and a low babble of other
+/// characters, returning an Ogg/Opus voice note. CPU-bound; call it off the async runtime.
+pub fn render_captcha_audio(
+    code: &str,
+    voice: &CaptchaVoice,
+    lang: AudioLang,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let samples = voice.samples(lang).ok_or("no audio captcha samples")?;
+    let mut rng = rand::thread_rng();
+    let rate = AUDIO_SAMPLE_RATE as f32;
+
+    let mut track: Vec<f32> = vec![0.0; (rate * 0.6) as usize];
+    for ch in code.chars() {
+        let sample = samples
+            .get(&ch.to_ascii_uppercase())
+            .ok_or("captcha character has no audio sample")?;
+        let gain = rng.gen_range(0.75..1.0);
+        track.extend(sample.iter().map(|s| s * gain));
+        let gap = rng.gen_range(0.35..0.6);
+        track.extend(std::iter::repeat_n(0.0, (rate * gap) as usize));
+    }
+
+    // Babble: quiet, time-reversed characters that are not part of the code.
+    let decoys: Vec<&Vec<f32>> = samples
+        .iter()
+        .filter(|(ch, _)| !code.contains(**ch))
+        .map(|(_, sample)| sample)
+        .collect();
+    for _ in 0..code.chars().count() {
+        let Some(decoy) = decoys.choose(&mut rng) else {
+            break;
+        };
+        let start = rng.gen_range(0..track.len());
+        let gain = rng.gen_range(0.12..0.22);
+        for (offset, s) in decoy.iter().rev().enumerate() {
+            if let Some(out) = track.get_mut(start + offset) {
+                *out += s * gain;
+            }
+        }
+    }
+
+    // Low-passed white noise so it sits under the voice instead of hissing over it.
+    let mut noise = 0.0f32;
+    for out in track.iter_mut() {
+        noise = noise * 0.7 + rng.gen_range(-1.0..1.0) * 0.3;
+        *out += noise * 0.12;
+    }
+
+    let peak = track.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+    let norm = if peak > 0.95 { 0.95 / peak } else { 1.0 };
+
+    let pcm: Vec<i16> = track
+        .iter()
+        .map(|s| (s * norm * i16::MAX as f32) as i16)
+        .collect();
+    encode_ogg_opus(&pcm)
+}
+
+/// Wraps 16 kHz mono PCM in an Ogg/Opus stream (RFC 7845), the format Telegram plays as a
+/// voice message.
+fn encode_ogg_opus(pcm: &[i16]) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let encoder = Encoder::new(SampleRate::Hz16000, Channels::Mono, Application::Voip)?;
+    let pre_skip = u64::from(encoder.lookahead()?) * OPUS_GRANULE_SCALE;
+    let serial = rand::random();
+    let mut writer = PacketWriter::new(Vec::new());
+
+    let mut head = b"OpusHead".to_vec();
+    head.push(1); // version
+    head.push(1); // channels
+    head.extend_from_slice(&(pre_skip as u16).to_le_bytes());
+    head.extend_from_slice(&AUDIO_SAMPLE_RATE.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes()); // output gain
+    head.push(0); // channel mapping family
+    writer.write_packet(head.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    let vendor = env!("CARGO_PKG_NAME").as_bytes();
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&0u32.to_le_bytes()); // no comments
+    writer.write_packet(tags.into(), serial, PacketWriteEndInfo::EndPage, 0)?;
+
+    let frames = pcm.len().div_ceil(OPUS_FRAME_LEN);
+    let mut frame = [0i16; OPUS_FRAME_LEN];
+    let mut packet = [0u8; 4000];
+    for (index, chunk) in pcm.chunks(OPUS_FRAME_LEN).enumerate() {
+        // The last frame is padded with silence; its granule position trims it again.
+        frame.fill(0);
+        frame[..chunk.len()].copy_from_slice(chunk);
+        let len = encoder.encode(&frame, &mut packet)?;
+        let last = index + 1 == frames;
+        let played = if last {
+            pcm.len()
+        } else {
+            (index + 1) * OPUS_FRAME_LEN
+        };
+        let info = if last {
+            PacketWriteEndInfo::EndStream
+        } else {
+            PacketWriteEndInfo::NormalPacket
+        };
+        let granule = pre_skip + played as u64 * OPUS_GRANULE_SCALE;
+        writer.write_packet(packet[..len].into(), serial, info, granule)?;
+    }
+    Ok(writer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha_alphabet::CAPTCHA_SAFE_CHARS;
+
+    fn tone_voice() -> CaptchaVoice {
+        let samples = CAPTCHA_SAFE_CHARS
+            .iter()
+            .enumerate()
+            .map(|(i, &ch)| {
+                let freq = 200.0 + i as f32 * 20.0;
+                let tone = (0..1600)
+                    .map(|n| (n as f32 * freq * 2.0 * std::f32::consts::PI / 16_000.0).sin() * 0.5)
+                    .collect();
+                (ch, tone)
+            })
+            .collect();
+        CaptchaVoice {
+            langs: HashMap::from([(AudioLang::Indonesian, samples)]),
+        }
+    }
+
+    #[test]
+    fn render_captcha_audio_writes_ogg_opus_and_falls_back_to_loaded_language() {
+        let ogg = render_captcha_audio("AB34", &tone_voice(), AudioLang::English).unwrap();
+        assert!(ogg.starts_with(b"OggS"));
+        assert_eq!(&ogg[28..36], b"OpusHead");
+        // Granule position of the last page: lead-in plus four 0.1 s characters and their
+        // gaps, counted at 48 kHz.
+        let last_page = ogg
+            .windows(4)
+            .rposition(|window| window == b"OggS")
+            .unwrap();
+        let granule = u64::from_le_bytes(ogg[last_page + 6..last_page + 14].try_into().unwrap());
+        assert!(granule > 48_000);
+    }
+
+    #[test]
+    fn builtin_voice_speaks_both_languages_or_nothing() {
+        let mut warnings = Vec::new();
+        let voice = CaptchaVoice::builtin(CAPTCHA_SAFE_CHARS, &mut warnings);
+        assert!(warnings.is_empty());
+        for lang in AudioLang::ALL {
+            assert_eq!(voice.langs[&lang].len(), CAPTCHA_SAFE_CHARS.len());
+        }
+        assert!(CaptchaVoice::builtin(&['A', '#'], &mut warnings).is_empty());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn captcha_voice_load_skips_missing_dir() {
+        let mut warnings = Vec::new();
+        let voice = CaptchaVoice::load(
+            Path::new("/nonexistent/voice"),
+            CAPTCHA_SAFE_CHARS,
+            &mut warnings,
+        );
+        assert!(voice.is_empty());
+        assert_eq!(warnings.len(), 1);
+    }
+}
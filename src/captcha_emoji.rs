@@ -0,0 +1,105 @@
+use std::error::Error;
+
+use rand::seq::SliceRandom;
+use teloxide::types::ChatId;
+
+use crate::captcha::{CaptchaChallenge, CaptchaMessageKind, ChallengeProvider};
+use crate::config::Config;
+use crate::utils::escape_html;
+
+/// Objects that are easy to tell apart at a glance, paired with the name shown in the caption.
+const CAPTCHA_EMOJI: &[(&str, &str)] = &[
+    ("🍉", "semangka"),
+    ("🍌", "pisang"),
+    ("🍎", "apel"),
+    ("🍇", "anggur"),
+    ("🍍", "nanas"),
+    ("🥕", "wortel"),
+    ("🌽", "jagung"),
+    ("🍄", "jamur"),
+    ("🐱", "kucing"),
+    ("🐶", "anjing"),
+    ("🐟", "ikan"),
+    ("🐔", "ayam"),
+    ("🐘", "gajah"),
+    ("🐢", "kura-kura"),
+    ("🦋", "kupu-kupu"),
+    ("🚗", "mobil"),
+    ("🚲", "sepeda"),
+    ("🚢", "kapal"),
+    ("⚽", "bola"),
+    ("🎸", "gitar"),
+    ("🔑", "kunci"),
+    ("📚", "buku"),
+    ("🌙", "bulan"),
+    ("🌵", "kaktus"),
+    ("🏠", "rumah"),
+    ("🎈", "balon"),
+    ("👟", "sepatu"),
+    ("🕯️", "lilin"),
+];
+
+pub fn generate_emoji_captcha(option_count: usize) -> CaptchaChallenge {
+    let mut rng = rand::thread_rng();
+    let target = option_count.clamp(2, CAPTCHA_EMOJI.len());
+    let picked: Vec<&(&str, &str)> = CAPTCHA_EMOJI.choose_multiple(&mut rng, target).collect();
+    let (emoji, name) = *picked[0];
+
+    let mut options: Vec<String> = picked.iter().map(|(e, _)| e.to_string()).collect();
+    options.shuffle(&mut rng);
+    CaptchaChallenge {
+        code: emoji.to_string(),
+        media: None,
+        options,
+        prompt: Some(name.to_string()),
+        data: serde_json::Value::Null,
+    }
+}
+
+/// Text message naming an object; the user taps the matching emoji button.
+pub struct EmojiChallenge;
+
+impl ChallengeProvider for EmojiChallenge {
+    fn name(&self) -> &'static str {
+        "emoji"
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Text
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        Ok(generate_emoji_captcha(
+            config.captcha_difficulty(chat_id).option_count,
+        ))
+    }
+
+    fn instruction(&self, prompt: Option<&str>) -> String {
+        format!(
+            "👆🏻 Tekan tombol bergambar <b>{}</b>.",
+            escape_html(prompt.unwrap_or("-"))
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emoji_captcha_prompt_matches_code() {
+        let challenge = generate_emoji_captcha(6);
+        assert_eq!(challenge.options.len(), 6);
+        assert!(challenge.options.contains(&challenge.code));
+        let prompt = challenge.prompt.unwrap();
+        assert!(
+            CAPTCHA_EMOJI
+                .iter()
+                .any(|(emoji, name)| *emoji == challenge.code && *name == prompt)
+        );
+    }
+}
@@ -0,0 +1,278 @@
+use std::error::Error;
+
+use rand::{Rng, seq::SliceRandom};
+use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::captcha::{
+    CaptchaChallenge, CaptchaControls, CaptchaMessageKind, ChallengeProvider, PendingCaptcha,
+    button_rows, pooled_challenge, press_data, push_control_row,
+};
+use crate::captcha_pool::PoolKey;
+use crate::captcha_render::{CaptchaFonts, GridShape, render_shape_grid};
+use crate::config::Config;
+use crate::utils::escape_html;
+
+const GRID_TILES: usize = 9;
+
+const GRID_COLOURS: &[(&str, [u8; 3])] = &[
+    ("merah", [205, 40, 40]),
+    ("biru", [35, 80, 205]),
+    ("hijau", [25, 150, 60]),
+    ("oranye", [235, 130, 15]),
+    ("ungu", [135, 45, 175]),
+];
+
+fn shape_name(shape: GridShape) -> &'static str {
+    match shape {
+        GridShape::Circle => "lingkaran",
+        GridShape::Square => "kotak",
+        GridShape::Triangle => "segitiga",
+        GridShape::Star => "bintang",
+    }
+}
+
+/// What the user has to select.
+#[derive(Clone, Copy, Debug)]
+enum GridCondition {
+    Shape(GridShape),
+    Colour(usize),
+    Both(GridShape, usize),
+}
+
+impl GridCondition {
+    fn matches(self, shape: GridShape, colour: usize) -> bool {
+        match self {
+            GridCondition::Shape(want) => shape == want,
+            GridCondition::Colour(want) => colour == want,
+            GridCondition::Both(want_shape, want_colour) => {
+                shape == want_shape && colour == want_colour
+            }
+        }
+    }
+
+    fn describe(self) -> String {
+        match self {
+            GridCondition::Shape(shape) => shape_name(shape).to_string(),
+            GridCondition::Colour(colour) => {
+                format!("bentuk berwarna {}", GRID_COLOURS[colour].0)
+            }
+            GridCondition::Both(shape, colour) => {
+                format!("{} {}", shape_name(shape), GRID_COLOURS[colour].0)
+            }
+        }
+    }
+}
+
+/// Nine generated shapes in several colours; the user toggles every tile matching a
+/// condition such as "semua lingkaran" and presses "✔️ Selesai".
+pub struct GridChallenge;
+
+impl ChallengeProvider for GridChallenge {
+    fn name(&self) -> &'static str {
+        "grid"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["shapes"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Photo
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        _chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        pooled_challenge(config, PoolKey::Grid)
+    }
+
+    fn instruction(&self, prompt: Option<&str>) -> String {
+        format!(
+            "🔲 Pilih <b>semua {}</b> pada gambar, lalu tekan ✔️ Selesai.",
+            escape_html(prompt.unwrap_or("-"))
+        )
+    }
+
+    fn keyboard(
+        &self,
+        options: &[String],
+        config: &Config,
+        nonce: &str,
+        refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        build_grid_keyboard(options, nonce, self.controls(config, refresh))
+    }
+
+    fn on_press(&self, pending: &mut PendingCaptcha, payload: &str) -> Option<String> {
+        if payload == "done" {
+            return Some(grid_answer(&grid_selected(&pending.provider_data)));
+        }
+        toggle_grid_tile(pending, payload.strip_prefix("tile")?.parse().ok()?);
+        None
+    }
+}
+
+/// Nine random tiles and a condition, drawn as a PNG.
+pub fn generate_grid_captcha(
+    fonts: &CaptchaFonts,
+) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+    let (condition, tiles) = generate_grid_tiles(&mut rand::thread_rng());
+    let code = grid_answer(
+        &tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, (shape, colour))| condition.matches(*shape, *colour))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>(),
+    );
+    let drawn: Vec<(GridShape, [u8; 3])> = tiles
+        .iter()
+        .map(|&(shape, colour)| (shape, jitter_colour(GRID_COLOURS[colour].1)))
+        .collect();
+    let png = render_shape_grid(&drawn, fonts)?;
+    Ok(CaptchaChallenge {
+        code,
+        media: Some(png),
+        options: grid_labels(&[]),
+        prompt: Some(condition.describe()),
+        data: serde_json::Value::Null,
+    })
+}
+
+/// Toggle buttons laid out like the tiles of the grid image, then "✔️ Selesai" and the
+/// controls.
+fn build_grid_keyboard(
+    labels: &[String],
+    nonce: &str,
+    controls: CaptchaControls,
+) -> InlineKeyboardMarkup {
+    let mut rows = button_rows(labels, 3, |index| {
+        press_data(nonce, &format!("tile{index}"))
+    });
+    rows.push(vec![InlineKeyboardButton::callback(
+        "✔️ Selesai",
+        press_data(nonce, "done"),
+    )]);
+    push_control_row(&mut rows, nonce, controls);
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// Picks a condition and nine tiles (shape, colour index) with two to four matches.
+fn generate_grid_tiles(rng: &mut impl Rng) -> (GridCondition, Vec<(GridShape, usize)>) {
+    let shape = *GridShape::ALL.choose(rng).expect("shapes");
+    let colour = rng.gen_range(0..GRID_COLOURS.len());
+    let condition = match rng.gen_range(0..3) {
+        0 => GridCondition::Shape(shape),
+        1 => GridCondition::Colour(colour),
+        _ => GridCondition::Both(shape, colour),
+    };
+    let matching = rng.gen_range(2..=4);
+    let mut tiles = Vec::with_capacity(GRID_TILES);
+    while tiles.len() < GRID_TILES {
+        let tile = (
+            *GridShape::ALL.choose(rng).expect("shapes"),
+            rng.gen_range(0..GRID_COLOURS.len()),
+        );
+        let want = tiles.len() < matching;
+        if condition.matches(tile.0, tile.1) == want {
+            tiles.push(tile);
+        }
+    }
+    tiles.shuffle(rng);
+    (condition, tiles)
+}
+
+fn jitter_colour(colour: [u8; 3]) -> [u8; 3] {
+    let mut rng = rand::thread_rng();
+    colour.map(|c| (c as i16 + rng.gen_range(-18..=18)).clamp(0, 255) as u8)
+}
+
+/// Answer string for a set of selected tiles: sorted 1-based tile numbers, e.g. `2,5,9`.
+fn grid_answer(selected: &[usize]) -> String {
+    let mut numbers: Vec<usize> = selected.iter().map(|index| index + 1).collect();
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbers
+        .iter()
+        .map(usize::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Button labels for the tiles, with selected ones ticked.
+fn grid_labels(selected: &[usize]) -> Vec<String> {
+    (0..GRID_TILES)
+        .map(|index| {
+            if selected.contains(&index) {
+                format!("✅ {}", index + 1)
+            } else {
+                (index + 1).to_string()
+            }
+        })
+        .collect()
+}
+
+/// Tiles selected so far, kept in the session's `provider_data`.
+fn grid_selected(data: &serde_json::Value) -> Vec<usize> {
+    serde_json::from_value(data.get("selected").cloned().unwrap_or_default()).unwrap_or_default()
+}
+
+/// Selects or deselects a tile and updates the button labels to match.
+fn toggle_grid_tile(pending: &mut PendingCaptcha, index: usize) {
+    if index >= GRID_TILES {
+        return;
+    }
+    let mut selected = grid_selected(&pending.provider_data);
+    if let Some(pos) = selected.iter().position(|&sel| sel == index) {
+        selected.remove(pos);
+    } else {
+        selected.push(index);
+    }
+    pending.options = grid_labels(&selected);
+    pending.provider_data = serde_json::json!({ "selected": selected });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generate_grid_tiles_has_two_to_four_matches() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let (condition, tiles) = generate_grid_tiles(&mut rng);
+            assert_eq!(tiles.len(), GRID_TILES);
+            let matches = tiles
+                .iter()
+                .filter(|(shape, colour)| condition.matches(*shape, *colour))
+                .count();
+            assert!((2..=4).contains(&matches));
+        }
+    }
+
+    #[test]
+    fn grid_answer_is_order_independent() {
+        assert_eq!(grid_answer(&[8, 1, 4]), "2,5,9");
+        assert_eq!(grid_answer(&[]), "");
+        let labels = grid_labels(&[2]);
+        assert_eq!(labels[2], "✅ 3");
+        assert_eq!(labels[0], "1");
+    }
+
+    #[test]
+    fn grid_presses_toggle_tiles_in_provider_data() {
+        let mut pending = crate::captcha::test_pending("grid", "2,5");
+        pending.options = grid_labels(&[]);
+        for payload in ["tile4", "tile8", "tile1", "tile8", "tile42", "key0"] {
+            assert_eq!(GridChallenge.on_press(&mut pending, payload), None);
+        }
+        assert_eq!(pending.options[4], "✅ 5");
+        assert_eq!(pending.options[8], "9");
+        assert_eq!(
+            GridChallenge.on_press(&mut pending, "done"),
+            Some("2,5".to_string())
+        );
+    }
+}
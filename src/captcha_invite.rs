@@ -0,0 +1,151 @@
+use teloxide::types::ChatInviteLink;
+
+use crate::utils::sanitize_log_text;
+
+/// How members joining through a particular invite link are treated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvitePolicy {
+    /// The chat's usual captcha.
+    Default,
+    /// No restriction and no captcha, e.g. links handed out at events.
+    Skip,
+    /// The full captcha with a single attempt and no image refreshes, even for users
+    /// trusted from a related chat.
+    Strict,
+    /// Ban right away, for links that leaked to spammers.
+    Ban,
+}
+
+impl InvitePolicy {
+    fn parse(raw: &str) -> Option<Self> {
+        match raw.trim().to_ascii_lowercase().as_str() {
+            "default" => Some(Self::Default),
+            "skip" => Some(Self::Skip),
+            "strict" => Some(Self::Strict),
+            "ban" => Some(Self::Ban),
+            _ => None,
+        }
+    }
+}
+
+/// Policies attached to invite links by link name or URL.
+#[derive(Clone, Debug, Default)]
+pub struct InvitePolicies {
+    rules: Vec<(String, InvitePolicy)>,
+}
+
+impl InvitePolicies {
+    /// `CAPTCHA_INVITE_LINK_POLICIES`: comma-separated `<link name or URL>=<policy>`.
+    pub fn parse(raw: &str, warnings: &mut Vec<String>) -> Self {
+        let mut rules = Vec::new();
+        for entry in raw
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let rule = entry.rsplit_once('=').and_then(|(link, policy)| {
+                let link = link.trim();
+                (!link.is_empty())
+                    .then_some(link)
+                    .zip(InvitePolicy::parse(policy))
+            });
+            match rule {
+                Some((link, policy)) => rules.push((link.to_string(), policy)),
+                None => warnings.push(format!(
+                    "CAPTCHA_INVITE_LINK_POLICIES entry '{}' ignored: expected <link name or URL>=skip|strict|ban|default",
+                    sanitize_log_text(entry)
+                )),
+            }
+        }
+        Self { rules }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Policy for a join through `link`; joins without a link or through a link without a
+    /// policy get [`InvitePolicy::Default`].
+    pub fn for_link(&self, link: Option<&ChatInviteLink>) -> InvitePolicy {
+        let Some(link) = link else {
+            return InvitePolicy::Default;
+        };
+        self.rules
+            .iter()
+            .find(|(key, _)| {
+                link.name
+                    .as_deref()
+                    .is_some_and(|name| name.trim().eq_ignore_ascii_case(key))
+                    || link_matches(&link.invite_link, key)
+            })
+            .map_or(InvitePolicy::Default, |(_, policy)| *policy)
+    }
+}
+
+/// Name of the link for logs: its name, or the URL when unnamed.
+pub fn invite_link_label(link: &ChatInviteLink) -> String {
+    sanitize_log_text(link.name.as_deref().unwrap_or(&link.invite_link))
+}
+
+/// Whether the URL Telegram reported matches a configured one. Links created by another
+/// admin arrive with their tail replaced by "…", so only the visible part is compared.
+fn link_matches(reported: &str, configured: &str) -> bool {
+    let strip = |url: &str| {
+        url.trim()
+            .trim_start_matches("https://")
+            .trim_start_matches("http://")
+            .trim_end_matches('/')
+            .to_string()
+    };
+    let reported = strip(reported);
+    let configured = strip(configured);
+    match reported.strip_suffix('…') {
+        Some(visible) => visible.len() >= 8 && configured.starts_with(visible),
+        None => reported == configured,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha::test_user;
+
+    fn link(url: &str, name: Option<&str>) -> ChatInviteLink {
+        ChatInviteLink {
+            invite_link: url.to_string(),
+            creator: test_user(),
+            creates_join_request: false,
+            is_primary: false,
+            is_revoked: false,
+            name: name.map(str::to_string),
+            expire_date: None,
+            member_limit: None,
+            pending_join_request_count: None,
+        }
+    }
+
+    #[test]
+    fn policies_match_by_name_or_url() {
+        let mut warnings = Vec::new();
+        let policies = InvitePolicies::parse(
+            "Acara Juli=skip, https://t.me/+AbCdEfGhIjK=ban, bocor=nuke,=strict",
+            &mut warnings,
+        );
+        assert_eq!(policies.len(), 2);
+        assert_eq!(warnings.len(), 2);
+
+        let named = link("https://t.me/+Zz…", Some("acara juli"));
+        assert_eq!(policies.for_link(Some(&named)), InvitePolicy::Skip);
+        let full = link("https://t.me/+AbCdEfGhIjK", None);
+        assert_eq!(policies.for_link(Some(&full)), InvitePolicy::Ban);
+        let hidden = link("https://t.me/+AbCd…", None);
+        assert_eq!(policies.for_link(Some(&hidden)), InvitePolicy::Ban);
+        let other = link("https://t.me/+Other", Some("umum"));
+        assert_eq!(policies.for_link(Some(&other)), InvitePolicy::Default);
+        assert_eq!(policies.for_link(None), InvitePolicy::Default);
+    }
+}
@@ -0,0 +1,195 @@
+use std::error::Error;
+
+use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup};
+
+use crate::captcha::{
+    CaptchaChallenge, CaptchaControls, CaptchaMessageKind, ChallengeProvider, PendingCaptcha,
+    button_rows, pooled_captcha, press_data, push_control_row,
+};
+use crate::captcha_labels::obfuscate_label;
+use crate::config::Config;
+
+/// Code rendered in an image and entered by tapping its characters in order on a keypad
+/// of the whole alphabet. Guessing has to get every character right instead of picking
+/// one of a handful of options.
+pub struct KeypadChallenge;
+
+impl ChallengeProvider for KeypadChallenge {
+    fn name(&self) -> &'static str {
+        "keypad"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["sequence", "tap"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Photo
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let difficulty = config.captcha_difficulty(chat_id);
+        let alphabet = config.captcha_alphabet(chat_id);
+        let (code, png) = pooled_captcha(config, alphabet, difficulty.len)?;
+        Ok(CaptchaChallenge {
+            code,
+            media: Some(png),
+            options: alphabet.chars().iter().map(char::to_string).collect(),
+            prompt: None,
+            data: serde_json::Value::Null,
+        })
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "🔢 Ketuk karakter pada gambar satu per satu sesuai urutan. Jawaban diperiksa setelah karakter terakhir."
+            .to_string()
+    }
+
+    fn keyboard(
+        &self,
+        options: &[String],
+        config: &Config,
+        nonce: &str,
+        refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        build_keypad_keyboard(
+            options,
+            config.captcha_option_obfuscate,
+            nonce,
+            self.controls(config, refresh),
+        )
+    }
+
+    fn speaks_code(&self) -> bool {
+        true
+    }
+
+    fn case_sensitive(&self, config: &Config) -> bool {
+        config.captcha_case_sensitive
+    }
+
+    fn on_press(&self, pending: &mut PendingCaptcha, payload: &str) -> Option<String> {
+        let press = match payload {
+            "back" => KeypadPress::Backspace,
+            key => KeypadPress::Key(key.strip_prefix("key")?.parse().ok()?),
+        };
+        apply_keypad_press(pending, press)
+    }
+
+    fn input_echo(&self, data: &serde_json::Value, code: &str) -> Option<String> {
+        Some(keypad_echo(keypad_input(data), code.chars().count()))
+    }
+}
+
+/// One button per character, then "⌫ Hapus" and the controls.
+fn build_keypad_keyboard(
+    keys: &[String],
+    obfuscate: bool,
+    nonce: &str,
+    controls: CaptchaControls,
+) -> InlineKeyboardMarkup {
+    let labels: Vec<String> = if obfuscate {
+        let mut rng = rand::thread_rng();
+        keys.iter()
+            .map(|key| obfuscate_label(key, &mut rng))
+            .collect()
+    } else {
+        keys.to_vec()
+    };
+    let mut rows = button_rows(&labels, 6, |index| {
+        press_data(nonce, &format!("key{index}"))
+    });
+    rows.push(vec![InlineKeyboardButton::callback(
+        "⌫ Hapus",
+        press_data(nonce, "back"),
+    )]);
+    push_control_row(&mut rows, nonce, controls);
+    InlineKeyboardMarkup::new(rows)
+}
+
+/// A keypad button press.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeypadPress {
+    Key(usize),
+    Backspace,
+}
+
+/// Characters tapped so far, kept in the session's `provider_data`.
+fn keypad_input(data: &serde_json::Value) -> &str {
+    data.get("input")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default()
+}
+
+/// Applies a press to the session's partial input. Returns the full input once it is as
+/// long as the code, clearing it for the next try; `None` while input is still going.
+fn apply_keypad_press(pending: &mut PendingCaptcha, press: KeypadPress) -> Option<String> {
+    let code_len = pending.code.chars().count();
+    let mut input = keypad_input(&pending.provider_data).to_string();
+    match press {
+        KeypadPress::Key(index) => {
+            let key = pending.options.get(index)?;
+            if input.chars().count() < code_len {
+                input.push_str(key);
+            }
+        }
+        KeypadPress::Backspace => {
+            input.pop();
+        }
+    }
+    let complete = input.chars().count() >= code_len;
+    pending.provider_data = serde_json::json!({ "input": if complete { "" } else { &input } });
+    complete.then_some(input)
+}
+
+/// Entered characters followed by a placeholder per missing one, e.g. `A B 7 _ _ _`.
+fn keypad_echo(input: &str, code_len: usize) -> String {
+    input
+        .chars()
+        .map(String::from)
+        .chain(std::iter::repeat_n(
+            "_".to_string(),
+            code_len.saturating_sub(input.chars().count()),
+        ))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha::test_pending;
+
+    #[test]
+    fn apply_keypad_press_builds_input_in_order() {
+        let mut pending = test_pending("keypad", "B7A");
+        pending.options = vec!["A".to_string(), "B".to_string(), "7".to_string()];
+
+        assert_eq!(apply_keypad_press(&mut pending, KeypadPress::Key(1)), None);
+        assert_eq!(apply_keypad_press(&mut pending, KeypadPress::Key(0)), None);
+        assert_eq!(
+            KeypadChallenge.input_echo(&pending.provider_data, &pending.code),
+            Some("B A _".to_string())
+        );
+        assert_eq!(
+            apply_keypad_press(&mut pending, KeypadPress::Backspace),
+            None
+        );
+        // Presses on keys that do not exist are ignored.
+        assert_eq!(apply_keypad_press(&mut pending, KeypadPress::Key(9)), None);
+        assert_eq!(apply_keypad_press(&mut pending, KeypadPress::Key(2)), None);
+        assert_eq!(
+            apply_keypad_press(&mut pending, KeypadPress::Key(0)),
+            Some("B7A".to_string())
+        );
+        assert_eq!(keypad_input(&pending.provider_data), "");
+        // Buttons reach the keypad through `on_press`.
+        assert_eq!(KeypadChallenge.on_press(&mut pending, "key1"), None);
+        assert_eq!(keypad_input(&pending.provider_data), "B");
+        assert_eq!(KeypadChallenge.on_press(&mut pending, "tile1"), None);
+    }
+}
@@ -0,0 +1,175 @@
+use rand::Rng;
+
+/// Letters and digits from other scripts (mostly Cyrillic and Greek) drawn like a Latin
+/// letter or digit. Unlike the styled forms below, NFKC does not map them back to ASCII.
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('A', 'А'),
+    ('B', 'В'),
+    ('C', 'С'),
+    ('D', 'Ꭰ'),
+    ('E', 'Е'),
+    ('F', 'Ϝ'),
+    ('G', 'Ԍ'),
+    ('H', 'Н'),
+    ('I', 'І'),
+    ('J', 'Ј'),
+    ('K', 'К'),
+    ('L', 'Ꮮ'),
+    ('M', 'М'),
+    ('N', 'Ν'),
+    ('O', 'О'),
+    ('P', 'Р'),
+    ('Q', 'Ԛ'),
+    ('R', 'Ꭱ'),
+    ('S', 'Ѕ'),
+    ('T', 'Т'),
+    ('U', 'Ս'),
+    ('V', 'Ѵ'),
+    ('W', 'Ԝ'),
+    ('X', 'Х'),
+    ('Y', 'Υ'),
+    ('Z', 'Ζ'),
+    ('a', 'а'),
+    ('c', 'с'),
+    ('d', 'ԁ'),
+    ('e', 'е'),
+    ('g', 'ɡ'),
+    ('h', 'һ'),
+    ('i', 'і'),
+    ('j', 'ј'),
+    ('n', 'ո'),
+    ('o', 'о'),
+    ('p', 'р'),
+    ('q', 'ԛ'),
+    ('s', 'ѕ'),
+    ('u', 'ս'),
+    ('v', 'ѵ'),
+    ('w', 'ԝ'),
+    ('x', 'х'),
+    ('y', 'у'),
+    ('3', 'З'),
+    ('4', 'Ꮞ'),
+    ('6', 'б'),
+];
+
+/// Invisible character placed after a styled form, which NFKC would otherwise turn back
+/// into the plain character.
+const NFKC_MARKER: char = '\u{2060}';
+
+/// Unicode forms of a Latin letter or digit. Each keeps the character recognisable while
+/// changing its code point.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LabelStyle {
+    Fullwidth,
+    Circled,
+    SansBold,
+}
+
+const LABEL_STYLES: &[LabelStyle] = &[
+    LabelStyle::Fullwidth,
+    LabelStyle::Circled,
+    LabelStyle::SansBold,
+];
+
+/// Rewrites the ASCII letters and digits of a button label so the label reads the same to
+/// people but no longer equals the OCR'd code, even after NFKC normalisation. Each
+/// character gets a random pick among its homoglyph, if any, and the styled forms; styled
+/// forms are followed by an invisible marker. Keycap emoji produced by the digit-to-emoji
+/// mapping are left intact.
+pub fn obfuscate_label(label: &str, rng: &mut impl Rng) -> String {
+    let mut out = String::with_capacity(label.len() * 4);
+    let mut chars = label.chars().peekable();
+    while let Some(ch) = chars.next() {
+        let keycap = chars.peek() == Some(&'\u{FE0F}');
+        if !ch.is_ascii_alphanumeric() || keycap {
+            out.push(ch);
+            continue;
+        }
+        let glyph = homoglyph(ch);
+        let pick = rng.gen_range(0..LABEL_STYLES.len() + usize::from(glyph.is_some()));
+        match LABEL_STYLES.get(pick) {
+            Some(&style) => {
+                out.push(styled_char(ch, style));
+                out.push(NFKC_MARKER);
+            }
+            None => out.push(glyph.unwrap_or(ch)),
+        }
+    }
+    out
+}
+
+fn homoglyph(ch: char) -> Option<char> {
+    HOMOGLYPHS
+        .iter()
+        .find(|(latin, _)| *latin == ch)
+        .map(|(_, glyph)| *glyph)
+}
+
+/// `ch` in the given form.
+fn styled_char(ch: char, style: LabelStyle) -> char {
+    let offset = |base: u32, first: char| char::from_u32(base + (ch as u32 - first as u32));
+    let styled = match (style, ch) {
+        (LabelStyle::Fullwidth, 'A'..='Z') => offset(0xFF21, 'A'),
+        (LabelStyle::Fullwidth, 'a'..='z') => offset(0xFF41, 'a'),
+        (LabelStyle::Fullwidth, '0'..='9') => offset(0xFF10, '0'),
+        (LabelStyle::Circled, 'A'..='Z') => offset(0x24B6, 'A'),
+        (LabelStyle::Circled, 'a'..='z') => offset(0x24D0, 'a'),
+        (LabelStyle::Circled, '0') => Some('⓪'),
+        (LabelStyle::Circled, '1'..='9') => offset(0x2460, '1'),
+        (LabelStyle::SansBold, 'A'..='Z') => offset(0x1D5D4, 'A'),
+        (LabelStyle::SansBold, 'a'..='z') => offset(0x1D5EE, 'a'),
+        (LabelStyle::SansBold, '0'..='9') => offset(0x1D7EC, '0'),
+        _ => None,
+    };
+    styled.unwrap_or(ch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn styled_char_maps_whole_alphabet() {
+        assert_eq!(styled_char('A', LabelStyle::Fullwidth), 'Ａ');
+        assert_eq!(styled_char('z', LabelStyle::Fullwidth), 'ｚ');
+        assert_eq!(styled_char('0', LabelStyle::Circled), '⓪');
+        assert_eq!(styled_char('9', LabelStyle::Circled), '⑨');
+        assert_eq!(styled_char('K', LabelStyle::Circled), 'Ⓚ');
+        assert_eq!(styled_char('7', LabelStyle::SansBold), '𝟳');
+        assert_eq!(homoglyph('C'), Some('С'));
+        for ch in ('A'..='Z').chain('a'..='z').chain('0'..='9') {
+            for &style in LABEL_STYLES {
+                assert_ne!(styled_char(ch, style), ch, "{ch} {style:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn obfuscate_label_changes_every_alphanumeric() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..50 {
+            let label = obfuscate_label("AB7x", &mut rng);
+            let visible: Vec<char> = label.chars().filter(|&ch| ch != NFKC_MARKER).collect();
+            assert_eq!(visible.len(), 4);
+            assert!(visible.iter().all(|ch| !ch.is_ascii_alphanumeric()));
+        }
+        // Keycap emoji from the digit mapping stay as they are.
+        assert_eq!(obfuscate_label("7️⃣ 🅰️", &mut rng), "7️⃣ 🅰️");
+    }
+
+    #[test]
+    fn obfuscated_labels_survive_nfkc() {
+        use unicode_normalization::UnicodeNormalization;
+
+        let mut rng = rand::thread_rng();
+        for ch in ('A'..='Z').chain('a'..='z').chain('0'..='9') {
+            for _ in 0..10 {
+                let label = obfuscate_label(&ch.to_string(), &mut rng);
+                let normalized: String = label.nfkc().collect();
+                assert_ne!(normalized, ch.to_string(), "{ch} -> {label}");
+            }
+        }
+        let normalized: String = obfuscate_label("K7P3", &mut rng).nfkc().collect();
+        assert_ne!(normalized, "K7P3");
+    }
+}
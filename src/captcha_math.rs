@@ -0,0 +1,333 @@
+use std::error::Error;
+
+use rand::{Rng, seq::SliceRandom};
+use teloxide::types::ChatId;
+
+use crate::captcha::{CaptchaChallenge, CaptchaMessageKind, ChallengeProvider, pooled_challenge};
+use crate::captcha_adaptive::CaptchaDifficulty;
+use crate::captcha_pool::PoolKey;
+use crate::captcha_render::{CaptchaFonts, render_expression_png};
+use crate::config::Config;
+
+/// Most answer buttons a math captcha gets, whatever the adaptive level asks for. Telegram
+/// quiz polls take at most 10 options, and small answers have few plausible distractors.
+const MAX_MATH_OPTIONS: usize = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MathOp {
+    Add,
+    Sub,
+    Mul,
+}
+
+impl MathOp {
+    fn symbol(self) -> char {
+        match self {
+            MathOp::Add => '+',
+            MathOp::Sub => '-',
+            MathOp::Mul => '×',
+        }
+    }
+
+    fn apply(self, left: i64, right: i64) -> i64 {
+        match self {
+            MathOp::Add => left + right,
+            MathOp::Sub => left - right,
+            MathOp::Mul => left * right,
+        }
+    }
+
+    fn binds_tighter(self) -> bool {
+        matches!(self, MathOp::Mul)
+    }
+}
+
+/// Three-operand expression `a op1 b op2 c`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MathExpr {
+    pub a: i64,
+    pub op1: MathOp,
+    pub b: i64,
+    pub op2: MathOp,
+    pub c: i64,
+}
+
+impl MathExpr {
+    pub fn answer(&self) -> i64 {
+        if self.op2.binds_tighter() && !self.op1.binds_tighter() {
+            self.op1.apply(self.a, self.op2.apply(self.b, self.c))
+        } else {
+            self.op2.apply(self.op1.apply(self.a, self.b), self.c)
+        }
+    }
+
+    /// Result when the expression is evaluated strictly left to right.
+    pub fn left_to_right(&self) -> i64 {
+        self.op2.apply(self.op1.apply(self.a, self.b), self.c)
+    }
+
+    /// Result when the last two operands are grouped first.
+    pub fn right_to_left(&self) -> i64 {
+        self.op1.apply(self.a, self.op2.apply(self.b, self.c))
+    }
+
+    pub fn text(&self) -> String {
+        format!(
+            "{} {} {} {} {}",
+            self.a,
+            self.op1.symbol(),
+            self.b,
+            self.op2.symbol(),
+            self.c
+        )
+    }
+}
+
+pub fn random_math_expr() -> MathExpr {
+    let mut rng = rand::thread_rng();
+    loop {
+        let expr = match rng.gen_range(0..3) {
+            0 => MathExpr {
+                a: rng.gen_range(1..=20),
+                op1: MathOp::Add,
+                b: rng.gen_range(2..=9),
+                op2: MathOp::Mul,
+                c: rng.gen_range(2..=9),
+            },
+            1 => MathExpr {
+                a: rng.gen_range(2..=9),
+                op1: MathOp::Mul,
+                b: rng.gen_range(2..=9),
+                op2: MathOp::Add,
+                c: rng.gen_range(1..=20),
+            },
+            _ => MathExpr {
+                a: rng.gen_range(20..=60),
+                op1: MathOp::Sub,
+                b: rng.gen_range(2..=6),
+                op2: MathOp::Mul,
+                c: rng.gen_range(2..=6),
+            },
+        };
+        if expr.answer() > 0 && expr.left_to_right() > 0 && expr.right_to_left() > 0 {
+            return expr;
+        }
+    }
+}
+
+/// Builds numeric answer options. Distractors come from typical mistakes first
+/// (ignoring operator precedence, off-by-one, dropping an operand) and are then
+/// padded with nearby numbers.
+pub fn generate_math_options(expr: &MathExpr, count: usize) -> Vec<String> {
+    let target = count.max(2);
+    let answer = expr.answer();
+    let mut values = vec![answer];
+
+    let mistakes = [
+        expr.left_to_right(),
+        expr.right_to_left(),
+        answer + 1,
+        answer - 1,
+        expr.a + expr.b + expr.c,
+        answer + expr.c,
+        answer - expr.c,
+        answer + 10,
+        answer - 10,
+    ];
+    for value in mistakes {
+        if values.len() >= target {
+            break;
+        }
+        if value > 0 && !values.contains(&value) {
+            values.push(value);
+        }
+    }
+
+    let mut rng = rand::thread_rng();
+    // Wide enough to hold `target` positive values even when the answer is 1.
+    let spread = (answer / 2).max(5).max(target as i64);
+    let mut tries = 0;
+    while values.len() < target && tries < 1000 {
+        tries += 1;
+        let value = answer + rng.gen_range(-spread..=spread);
+        if value > 0 && !values.contains(&value) {
+            values.push(value);
+        }
+    }
+
+    let mut options: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    options.shuffle(&mut rng);
+    options
+}
+
+/// Same challenge as [`generate_math_captcha`] but without an image; the expression is
+/// returned as the prompt so it can be asked in plain text (e.g. as a quiz poll).
+pub fn generate_math_question(option_count: usize) -> CaptchaChallenge {
+    let expr = random_math_expr();
+    CaptchaChallenge {
+        code: expr.answer().to_string(),
+        media: None,
+        options: generate_math_options(&expr, option_count),
+        prompt: Some(expr.text()),
+        data: serde_json::Value::Null,
+    }
+}
+
+pub fn generate_math_captcha(
+    fonts: &CaptchaFonts,
+    width: u32,
+    height: u32,
+    option_count: usize,
+) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+    let expr = random_math_expr();
+    // Spaces would take up a glyph cell each.
+    let expression = format!("{}=?", expr.text().replace(' ', ""));
+    let png = render_expression_png(&expression, fonts, width, height)?;
+    Ok(CaptchaChallenge {
+        code: expr.answer().to_string(),
+        media: Some(png),
+        options: generate_math_options(&expr, option_count),
+        prompt: None,
+        data: serde_json::Value::Null,
+    })
+}
+
+pub fn math_option_count(difficulty: CaptchaDifficulty) -> usize {
+    difficulty.option_count.min(MAX_MATH_OPTIONS)
+}
+
+/// Arithmetic problem rendered in an image, answered with numeric buttons.
+pub struct MathChallenge;
+
+impl ChallengeProvider for MathChallenge {
+    fn name(&self) -> &'static str {
+        "math"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["arithmetic"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Photo
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        pooled_challenge(
+            config,
+            PoolKey::Math(math_option_count(config.captcha_difficulty(chat_id))),
+        )
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "🧮 Hitung soal pada gambar, lalu pilih hasilnya dari tombol.".to_string()
+    }
+}
+
+/// Arithmetic problem asked as a native Telegram quiz poll.
+pub struct PollChallenge;
+
+impl ChallengeProvider for PollChallenge {
+    fn name(&self) -> &'static str {
+        "poll"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["quiz"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Poll
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        Ok(generate_math_question(math_option_count(
+            config.captcha_difficulty(chat_id),
+        )))
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "📊 Jawab kuis di bawah ini.".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha_adaptive::MAX_ADAPTIVE_LEVEL;
+
+    #[test]
+    fn math_expr_respects_precedence() {
+        let expr = MathExpr {
+            a: 7,
+            op1: MathOp::Add,
+            b: 5,
+            op2: MathOp::Mul,
+            c: 2,
+        };
+        assert_eq!(expr.answer(), 17);
+        assert_eq!(expr.left_to_right(), 24);
+        assert_eq!(expr.text(), "7 + 5 × 2");
+    }
+
+    #[test]
+    fn math_options_include_answer_and_precedence_mistake() {
+        let expr = MathExpr {
+            a: 7,
+            op1: MathOp::Add,
+            b: 5,
+            op2: MathOp::Mul,
+            c: 2,
+        };
+        let options = generate_math_options(&expr, 6);
+        assert_eq!(options.len(), 6);
+        assert!(options.contains(&"17".to_string()));
+        assert!(options.contains(&"24".to_string()));
+    }
+
+    #[test]
+    fn math_options_fill_up_for_small_answers() {
+        let expr = MathExpr {
+            a: 21,
+            op1: MathOp::Sub,
+            b: 4,
+            op2: MathOp::Mul,
+            c: 5,
+        };
+        assert_eq!(expr.answer(), 1);
+        let options = generate_math_options(&expr, 12);
+        assert_eq!(options.len(), 12);
+        assert!(options.contains(&"1".to_string()));
+    }
+
+    #[test]
+    fn raised_difficulty_keeps_math_options_capped() {
+        let expr = MathExpr {
+            a: 21,
+            op1: MathOp::Sub,
+            b: 4,
+            op2: MathOp::Mul,
+            c: 5,
+        };
+        for level in 0..=MAX_ADAPTIVE_LEVEL {
+            let count = math_option_count(CaptchaDifficulty::at_level(level, 6, 12, 120));
+            assert!(count <= MAX_MATH_OPTIONS);
+            assert_eq!(generate_math_options(&expr, count).len(), count);
+        }
+    }
+
+    #[test]
+    fn math_captcha_renders_png() {
+        let challenge = generate_math_captcha(&CaptchaFonts::default(), 320, 100, 4).unwrap();
+        assert!(challenge.media.unwrap().starts_with(b"\x89PNG"));
+        assert!(challenge.options.contains(&challenge.code));
+    }
+}
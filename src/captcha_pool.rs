@@ -0,0 +1,269 @@
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::captcha::{CaptchaChallenge, generate_animated_captcha, generate_captcha};
+use crate::captcha_alphabet::CaptchaAlphabet;
+use crate::captcha_grid::generate_grid_captcha;
+use crate::captcha_math::generate_math_captcha;
+use crate::captcha_render::CaptchaFonts;
+
+/// Distinct challenge kinds kept warm. Adaptive levels and per-chat alphabets add keys as
+/// they are first asked for; anything past this renders on demand.
+const MAX_POOL_KEYS: usize = 16;
+
+/// How often pool depth and hit rate are logged (only when the pool was used).
+pub const CAPTCHA_POOL_LOG_INTERVAL: Duration = Duration::from_secs(300);
+
+/// Which challenge a queue holds.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PoolKey {
+    /// Static PNG code with this alphabet and length.
+    Image(CaptchaAlphabet, usize),
+    /// Animated GIF code with this alphabet and length.
+    Animated(CaptchaAlphabet, usize),
+    /// Arithmetic image with this many answer options.
+    Math(usize),
+    /// Shape grid.
+    Grid,
+}
+
+impl PoolKey {
+    /// Renders one challenge of this kind. Code captchas come back without options; the
+    /// caller builds those for its chat.
+    pub fn render(
+        &self,
+        fonts: &CaptchaFonts,
+        width: u32,
+        height: u32,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let coded = |(code, media): (String, Vec<u8>)| CaptchaChallenge {
+            code,
+            media: Some(media),
+            options: Vec::new(),
+            prompt: None,
+            data: serde_json::Value::Null,
+        };
+        match self {
+            PoolKey::Image(alphabet, len) => {
+                generate_captcha(fonts, alphabet, *len, width, height).map(coded)
+            }
+            PoolKey::Animated(alphabet, len) => {
+                generate_animated_captcha(fonts, alphabet, *len, width, height).map(coded)
+            }
+            PoolKey::Math(option_count) => {
+                generate_math_captcha(fonts, width, height, *option_count)
+            }
+            PoolKey::Grid => generate_grid_captcha(fonts),
+        }
+    }
+}
+
+/// Snapshot for the periodic pool log line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CaptchaPoolStats {
+    pub ready: usize,
+    pub capacity: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CaptchaPoolStats {
+    pub fn hit_rate_percent(&self) -> u64 {
+        (self.hits * 100)
+            .checked_div(self.hits + self.misses)
+            .unwrap_or(0)
+    }
+}
+
+/// Image, animated, math and grid captchas rendered ahead of time on a dedicated thread,
+/// so join floods do not spend tokio worker time on drawing and PNG/GIF encoding.
+pub struct CaptchaPool {
+    depth: usize,
+    fonts: CaptchaFonts,
+    width: u32,
+    height: u32,
+    queues: Mutex<HashMap<PoolKey, VecDeque<CaptchaChallenge>>>,
+    wake: Condvar,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl fmt::Debug for CaptchaPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptchaPool")
+            .field("depth", &self.depth)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl CaptchaPool {
+    /// Pool of `depth` captchas per key, pre-seeded with the given keys.
+    pub fn new(
+        depth: usize,
+        fonts: CaptchaFonts,
+        width: u32,
+        height: u32,
+        warm: impl IntoIterator<Item = PoolKey>,
+    ) -> Self {
+        let queues = warm
+            .into_iter()
+            .take(MAX_POOL_KEYS)
+            .map(|key| (key, VecDeque::with_capacity(depth)))
+            .collect();
+        Self {
+            depth,
+            fonts,
+            width,
+            height,
+            queues: Mutex::new(queues),
+            wake: Condvar::new(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts the render thread; it runs for the life of the process.
+    pub fn spawn_worker(self: &Arc<Self>) -> std::io::Result<()> {
+        let pool = Arc::clone(self);
+        thread::Builder::new()
+            .name("captcha-pool".to_string())
+            .spawn(move || pool.run_worker())
+            .map(drop)
+    }
+
+    /// A pre-rendered captcha, or `None` when the queue is empty and the caller has to
+    /// render one itself. Unknown keys are queued for the worker to start filling.
+    pub fn take(&self, key: &PoolKey) -> Option<CaptchaChallenge> {
+        let mut queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+        let ready = match queues.get_mut(key) {
+            Some(queue) => queue.pop_front(),
+            None => {
+                if queues.len() < MAX_POOL_KEYS {
+                    queues.insert(key.clone(), VecDeque::with_capacity(self.depth));
+                }
+                None
+            }
+        };
+        drop(queues);
+        let counter = if ready.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.wake.notify_one();
+        ready
+    }
+
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn stats(&self) -> CaptchaPoolStats {
+        let queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+        CaptchaPoolStats {
+            ready: queues.values().map(VecDeque::len).sum(),
+            capacity: queues.len() * self.depth,
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    fn run_worker(&self) {
+        loop {
+            let key = {
+                let mut queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+                loop {
+                    let emptiest = queues
+                        .iter()
+                        .filter(|(_, queue)| queue.len() < self.depth)
+                        .min_by_key(|(_, queue)| queue.len())
+                        .map(|(key, _)| key.clone());
+                    if let Some(key) = emptiest {
+                        break key;
+                    }
+                    queues = self
+                        .wake
+                        .wait(queues)
+                        .unwrap_or_else(|err| err.into_inner());
+                }
+            };
+            match key.render(&self.fonts, self.width, self.height) {
+                Ok(rendered) => {
+                    let mut queues = self.queues.lock().unwrap_or_else(|err| err.into_inner());
+                    if let Some(queue) = queues.get_mut(&key) {
+                        queue.push_back(rendered);
+                    }
+                }
+                // Handlers render on demand (and report the error) while the pool backs off.
+                Err(_) => thread::sleep(Duration::from_secs(5)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait_until_ready(pool: &CaptchaPool, ready: usize) {
+        for _ in 0..200 {
+            if pool.stats().ready == ready {
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn worker_fills_queue_and_take_counts_hits() {
+        let alphabet = CaptchaAlphabet::default();
+        let key = PoolKey::Image(alphabet.clone(), 4);
+        let pool = Arc::new(CaptchaPool::new(
+            2,
+            CaptchaFonts::default(),
+            160,
+            60,
+            [key.clone()],
+        ));
+        // Nothing rendered yet: a miss.
+        assert!(pool.take(&key).is_none());
+        pool.spawn_worker().unwrap();
+        wait_until_ready(&pool, 2);
+        let challenge = pool.take(&key).expect("pre-rendered captcha");
+        assert_eq!(challenge.code.len(), 4);
+        assert!(challenge.media.unwrap().starts_with(b"\x89PNG"));
+
+        // A new length is registered on first use and filled later.
+        assert!(pool.take(&PoolKey::Image(alphabet, 6)).is_none());
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 2));
+        assert_eq!(stats.capacity, 4);
+        assert_eq!(stats.hit_rate_percent(), 33);
+    }
+
+    #[test]
+    fn worker_renders_math_and_grid_challenges() {
+        let pool = Arc::new(CaptchaPool::new(
+            1,
+            CaptchaFonts::default(),
+            320,
+            100,
+            [PoolKey::Math(4), PoolKey::Grid],
+        ));
+        pool.spawn_worker().unwrap();
+        wait_until_ready(&pool, 2);
+        let math = pool.take(&PoolKey::Math(4)).expect("pre-rendered math");
+        assert_eq!(math.options.len(), 4);
+        assert!(math.options.contains(&math.code));
+        let grid = pool.take(&PoolKey::Grid).expect("pre-rendered grid");
+        assert!(grid.prompt.is_some());
+        assert!(grid.media.unwrap().starts_with(b"\x89PNG"));
+    }
+}
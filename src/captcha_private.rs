@@ -0,0 +1,117 @@
+use std::error::Error;
+
+use teloxide::types::{ChatId, InlineKeyboardButton, InlineKeyboardMarkup};
+use url::Url;
+
+use crate::captcha::{
+    CaptchaChallenge, CaptchaMessageKind, ChallengeProvider, PendingCaptcha, PrivateStart,
+};
+use crate::config::Config;
+
+/// Prefix of the `/start` parameter that continues a pending captcha in the bot's private
+/// chat; the rest is the session nonce.
+pub const VERIFY_START_PREFIX: &str = "verify_";
+
+/// `t.me` link that opens the bot's private chat for the session `nonce`. `None` until the
+/// bot username is known.
+pub fn verify_start_link(config: &Config, nonce: &str) -> Option<Url> {
+    start_link(config.bot_username.get()?, nonce)
+}
+
+fn start_link(username: &str, nonce: &str) -> Option<Url> {
+    Url::parse(&format!(
+        "https://t.me/{username}?start={VERIFY_START_PREFIX}{nonce}"
+    ))
+    .ok()
+}
+
+/// "🔐 Verifikasi" button carrying the deep link, or no buttons without a bot username.
+pub fn verify_start_keyboard(config: &Config, label: &str, nonce: &str) -> InlineKeyboardMarkup {
+    match verify_start_link(config, nonce) {
+        Some(link) => InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::url(label, link)]]),
+        None => InlineKeyboardMarkup::default(),
+    }
+}
+
+/// Stand-in posted to the group with `CAPTCHA_PRIVATE_VERIFY`: a short message whose button
+/// opens the bot's private chat, where the chat's real challenge is sent. It is never
+/// answered itself and is not selectable through `CAPTCHA_MODE`.
+pub struct PrivateVerifyStub;
+
+impl ChallengeProvider for PrivateVerifyStub {
+    fn name(&self) -> &'static str {
+        "private"
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Text
+    }
+
+    fn available(&self, config: &Config, _chat_id: ChatId) -> bool {
+        config.captcha_private_verify && config.bot_username.get().is_some()
+    }
+
+    fn generate(
+        &self,
+        _config: &Config,
+        _chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        Ok(CaptchaChallenge {
+            code: String::new(),
+            media: None,
+            options: Vec::new(),
+            prompt: None,
+            data: serde_json::Value::Null,
+        })
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "🔐 Tekan <b>Verifikasi</b>, lalu <b>Start</b> di chat bot dan jawab captcha di sana."
+            .to_string()
+    }
+
+    fn keyboard(
+        &self,
+        _options: &[String],
+        config: &Config,
+        nonce: &str,
+        _refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        verify_start_keyboard(config, "🔐 Verifikasi", nonce)
+    }
+
+    fn check_answer(&self, _pending: &PendingCaptcha, _answer: &str) -> bool {
+        false
+    }
+
+    fn private_start(&self) -> Option<PrivateStart> {
+        Some(PrivateStart::MoveChallenge)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha::{challenge_provider, pending_provider};
+
+    #[test]
+    fn start_link_points_at_bot_chat() {
+        assert_eq!(
+            start_link("buktikan_bot", "abc").unwrap().as_str(),
+            "https://t.me/buktikan_bot?start=verify_abc"
+        );
+    }
+
+    #[test]
+    fn stub_is_resolvable_but_not_selectable() {
+        assert!(challenge_provider("private").is_none());
+        let raw = r#"{"code":"","provider":"private","captcha_message_id":{"message_id":10},
+            "options":[],"attempts_left":3,"attempts_total":3,"remaining_secs":120,
+            "expires_at":0,"user_display":"User","user_name":"User","user_username":null,
+            "chat_title":null,"chat_username":null}"#;
+        let pending: PendingCaptcha = serde_json::from_str(raw).unwrap();
+        let provider = pending_provider(&pending);
+        assert_eq!(provider.name(), PrivateVerifyStub.name());
+        assert!(!provider.check_answer(&pending, ""));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use teloxide::types::{ChatId, InlineKeyboardMarkup};
+
+use crate::captcha::{
+    CaptchaChallenge, CaptchaControls, CaptchaMessageKind, ChallengeProvider,
+    build_captcha_keyboard,
+};
+use crate::config::Config;
+use crate::utils::{escape_html, sanitize_log_text};
+
+/// Answers are button labels; keep them short enough to read on a phone.
+const MAX_ANSWER_BYTES: usize = 56;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct QuestionEntry {
+    pub question: String,
+    pub answer: String,
+    #[serde(default)]
+    pub distractors: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QuestionBank {
+    global: Vec<QuestionEntry>,
+    chats: HashMap<i64, Vec<QuestionEntry>>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum QuestionBankFile {
+    List(Vec<QuestionEntry>),
+    Scoped {
+        #[serde(default)]
+        global: Vec<QuestionEntry>,
+        #[serde(default)]
+        chats: HashMap<String, Vec<QuestionEntry>>,
+    },
+}
+
+impl QuestionBank {
+    /// Loads a bank from a `.json`, `.yaml` or `.yml` file. Invalid entries are skipped and
+    /// reported through `warnings`.
+    pub fn load(
+        path: &str,
+        warnings: &mut Vec<String>,
+    ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let raw = std::fs::read_to_string(path)?;
+        let is_yaml = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml"));
+        let file: QuestionBankFile = if is_yaml {
+            serde_yaml::from_str(&raw)?
+        } else {
+            serde_json::from_str(&raw)?
+        };
+        Ok(Self::from_file(file, warnings))
+    }
+
+    fn from_file(file: QuestionBankFile, warnings: &mut Vec<String>) -> Self {
+        let (global, chats) = match file {
+            QuestionBankFile::List(global) => (global, HashMap::new()),
+            QuestionBankFile::Scoped { global, chats } => (global, chats),
+        };
+        let global = validate_entries(global, "global", warnings);
+        let mut scoped = HashMap::new();
+        for (raw_id, entries) in chats {
+            let Ok(chat_id) = raw_id.trim().parse::<i64>() else {
+                warnings.push(format!(
+                    "question bank: chat id invalid ('{}'), ignoring",
+                    sanitize_log_text(&raw_id)
+                ));
+                continue;
+            };
+            let entries = validate_entries(entries, &raw_id, warnings);
+            if !entries.is_empty() {
+                scoped.insert(chat_id, entries);
+            }
+        }
+        Self {
+            global,
+            chats: scoped,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.global.is_empty() && self.chats.is_empty()
+    }
+
+    pub fn question_count(&self) -> usize {
+        self.global.len() + self.chats.values().map(Vec::len).sum::<usize>()
+    }
+
+    /// Chat-specific questions win; otherwise the global list is used.
+    pub fn questions_for(&self, chat_id: ChatId) -> &[QuestionEntry] {
+        match self.chats.get(&chat_id.0) {
+            Some(entries) => entries,
+            None => &self.global,
+        }
+    }
+}
+
+fn validate_entries(
+    entries: Vec<QuestionEntry>,
+    scope: &str,
+    warnings: &mut Vec<String>,
+) -> Vec<QuestionEntry> {
+    let mut out = Vec::with_capacity(entries.len());
+    for (idx, mut entry) in entries.into_iter().enumerate() {
+        entry.question = entry.question.trim().to_string();
+        entry.answer = entry.answer.trim().to_string();
+        entry.distractors = entry
+            .distractors
+            .iter()
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty() && !d.eq_ignore_ascii_case(&entry.answer))
+            .collect();
+        let mut seen: Vec<String> = Vec::new();
+        entry.distractors.retain(|d| {
+            if seen.iter().any(|s| s.eq_ignore_ascii_case(d)) {
+                false
+            } else {
+                seen.push(d.clone());
+                true
+            }
+        });
+
+        let too_long = std::iter::once(&entry.answer)
+            .chain(entry.distractors.iter())
+            .any(|opt| opt.len() > MAX_ANSWER_BYTES);
+        if entry.question.is_empty() || entry.answer.is_empty() || entry.distractors.is_empty() {
+            warnings.push(format!(
+                "question bank: {} entry #{} needs a question, an answer and at least one distractor; skipped",
+                sanitize_log_text(scope),
+                idx + 1
+            ));
+            continue;
+        }
+        if too_long {
+            warnings.push(format!(
+                "question bank: {} entry #{} has an option longer than {} bytes; skipped",
+                sanitize_log_text(scope),
+                idx + 1,
+                MAX_ANSWER_BYTES
+            ));
+            continue;
+        }
+        out.push(entry);
+    }
+    out
+}
+
+pub fn generate_question_captcha(
+    bank: &QuestionBank,
+    chat_id: ChatId,
+    option_count: usize,
+) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+    let mut rng = rand::thread_rng();
+    let entry = bank
+        .questions_for(chat_id)
+        .choose(&mut rng)
+        .ok_or("question bank has no questions for this chat")?;
+    Ok(CaptchaChallenge {
+        code: entry.answer.clone(),
+        media: None,
+        options: generate_question_options(entry, option_count),
+        prompt: Some(entry.question.clone()),
+        data: serde_json::Value::Null,
+    })
+}
+
+/// Admin-provided question with its answer and distractors as buttons.
+pub struct QuestionChallenge;
+
+impl ChallengeProvider for QuestionChallenge {
+    fn name(&self) -> &'static str {
+        "question"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["questions"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Text
+    }
+
+    fn available(&self, config: &Config, chat_id: ChatId) -> bool {
+        config
+            .captcha_question_bank
+            .as_ref()
+            .is_some_and(|bank| !bank.questions_for(chat_id).is_empty())
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let bank = config
+            .captcha_question_bank
+            .as_ref()
+            .ok_or("question bank not loaded")?;
+        generate_question_captcha(
+            bank,
+            chat_id,
+            config.captcha_difficulty(chat_id).option_count,
+        )
+    }
+
+    fn instruction(&self, prompt: Option<&str>) -> String {
+        format!(
+            "❓ <b>{}</b>\n💁🏻‍♂️ Pilih jawaban yang benar dari tombol yang tersedia.",
+            escape_html(prompt.unwrap_or("-"))
+        )
+    }
+
+    // Answers are free text, so digits and A/B are left as typed.
+    fn keyboard(
+        &self,
+        options: &[String],
+        _config: &Config,
+        nonce: &str,
+        _refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        build_captcha_keyboard(options, false, false, nonce, CaptchaControls::default())
+    }
+}
+
+/// Picks random distractors for the entry and shuffles them together with the answer.
+pub fn generate_question_options(entry: &QuestionEntry, count: usize) -> Vec<String> {
+    let mut rng = rand::thread_rng();
+    let target = count.max(2) - 1;
+    let mut options: Vec<String> = entry
+        .distractors
+        .choose_multiple(&mut rng, target)
+        .cloned()
+        .collect();
+    options.push(entry.answer.clone());
+    options.shuffle(&mut rng);
+    options
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn question_bank_prefers_chat_specific_entries() {
+        let raw = r#"
+global:
+  - question: "Ibu kota Indonesia?"
+    answer: "Jakarta"
+    distractors: ["Bandung", "Surabaya"]
+chats:
+  "-100123":
+    - question: "Apa fungsi `cargo build`?"
+      answer: "Mengompilasi proyek"
+      distractors: ["Menghapus proyek", "Memasang Rust"]
+    - question: "Tanpa distraktor"
+      answer: "x"
+"#;
+        let file: QuestionBankFile = serde_yaml::from_str(raw).unwrap();
+        let mut warnings = Vec::new();
+        let bank = QuestionBank::from_file(file, &mut warnings);
+        assert_eq!(bank.question_count(), 2);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            bank.questions_for(ChatId(-100123))[0].answer,
+            "Mengompilasi proyek"
+        );
+        assert_eq!(bank.questions_for(ChatId(-1))[0].answer, "Jakarta");
+
+        let challenge = generate_question_captcha(&bank, ChatId(-1), 6).unwrap();
+        assert_eq!(challenge.options.len(), 3);
+        assert!(challenge.options.contains(&challenge.code));
+    }
+
+    #[test]
+    fn question_bank_accepts_plain_json_list() {
+        let raw = r#"[{"question": "2 + 2?", "answer": "4", "distractors": ["5"]}]"#;
+        let file: QuestionBankFile = serde_json::from_str(raw).unwrap();
+        let mut warnings = Vec::new();
+        let bank = QuestionBank::from_file(file, &mut warnings);
+        assert!(warnings.is_empty());
+        assert_eq!(bank.questions_for(ChatId(-5)).len(), 1);
+    }
+}
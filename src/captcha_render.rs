@@ -0,0 +1,590 @@
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::io::Cursor;
+
+use ab_glyph::{Font, FontArc, PxScale, ScaleFont, point};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame, ImageOutputFormat, Rgb, RgbImage};
+use rand::{Rng, seq::SliceRandom};
+
+use crate::utils::sanitize_log_text;
+
+const BUNDLED_FONTS: &[&[u8]] = &[
+    include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf"),
+    include_bytes!("../assets/fonts/DejaVuSerif-Bold.ttf"),
+];
+
+/// Background colour plus the inks used for glyphs and interference lines.
+struct Palette {
+    background: [u8; 3],
+    inks: &'static [[u8; 3]],
+}
+
+const PALETTES: &[Palette] = &[
+    Palette {
+        background: [246, 244, 236],
+        inks: &[[28, 50, 110], [120, 30, 60], [20, 90, 70], [70, 40, 120]],
+    },
+    Palette {
+        background: [232, 242, 250],
+        inks: &[[10, 60, 120], [160, 60, 20], [40, 40, 40], [0, 110, 110]],
+    },
+    Palette {
+        background: [250, 238, 232],
+        inks: &[[90, 20, 20], [30, 80, 30], [40, 30, 100], [110, 70, 0]],
+    },
+    Palette {
+        background: [238, 248, 236],
+        inks: &[[20, 70, 20], [100, 20, 90], [20, 50, 120], [130, 50, 10]],
+    },
+];
+
+/// Fonts used for captcha glyphs. Every glyph picks one at random.
+#[derive(Clone)]
+pub struct CaptchaFonts {
+    fonts: Vec<FontArc>,
+}
+
+impl fmt::Debug for CaptchaFonts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CaptchaFonts")
+            .field("count", &self.fonts.len())
+            .finish()
+    }
+}
+
+impl Default for CaptchaFonts {
+    fn default() -> Self {
+        let fonts = BUNDLED_FONTS
+            .iter()
+            .map(|bytes| FontArc::try_from_slice(bytes).expect("bundled font is valid"))
+            .collect();
+        Self { fonts }
+    }
+}
+
+impl CaptchaFonts {
+    /// Loads TTF/OTF files. Unreadable files are reported through `warnings`; when none
+    /// load, the bundled fonts are used.
+    pub fn load(paths: &[String], warnings: &mut Vec<String>) -> Self {
+        let mut fonts = Vec::new();
+        for path in paths {
+            let loaded = std::fs::read(path)
+                .map_err(|err| err.to_string())
+                .and_then(|bytes| FontArc::try_from_vec(bytes).map_err(|err| err.to_string()));
+            match loaded {
+                Ok(font) => fonts.push(font),
+                Err(err) => warnings.push(format!(
+                    "CAPTCHA_FONT_PATHS font failed to load ('{}'): {}",
+                    sanitize_log_text(path),
+                    err
+                )),
+            }
+        }
+        if fonts.is_empty() {
+            Self::default()
+        } else {
+            Self { fonts }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fonts.len()
+    }
+}
+
+/// How strongly text is distorted.
+struct TextStyle {
+    /// Largest glyph rotation, in radians.
+    tilt: f32,
+    /// Whether interference lines are also drawn over the glyphs, not just behind them.
+    waves_over: bool,
+}
+
+const CODE_STYLE: TextStyle = TextStyle {
+    tilt: 0.45,
+    waves_over: true,
+};
+
+/// Arithmetic needs gentler distortion: a tilted `+` reads as `×`, and a line drawn over a
+/// `-` hides it.
+const EXPRESSION_STYLE: TextStyle = TextStyle {
+    tilt: 0.15,
+    waves_over: false,
+};
+
+/// Renders `code` as a distorted PNG of exactly `width` x `height` pixels.
+pub fn render_captcha_png(
+    code: &str,
+    fonts: &CaptchaFonts,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    render_png(code, fonts, width, height, &CODE_STYLE)
+}
+
+/// Renders an arithmetic expression such as `7+5×2=?` like [`render_captcha_png`], but
+/// distorted less so the operators stay readable.
+pub fn render_expression_png(
+    expression: &str,
+    fonts: &CaptchaFonts,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    render_png(expression, fonts, width, height, &EXPRESSION_STYLE)
+}
+
+fn render_png(
+    text: &str,
+    fonts: &CaptchaFonts,
+    width: u32,
+    height: u32,
+    style: &TextStyle,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut rng = rand::thread_rng();
+    let palette = PALETTES.choose(&mut rng).ok_or("no captcha palette")?;
+    let glyphs = layout_glyphs(text, fonts, palette, width, height, style.tilt, &mut rng)?;
+    let mut img = noisy_background(palette, width, height, &mut rng)?;
+    for glyph in &glyphs {
+        glyph.draw(&mut img, 0.0);
+    }
+    if style.waves_over {
+        draw_waves(&mut img, palette, &mut rng)?;
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(img).write_to(&mut out, ImageOutputFormat::Png)?;
+    Ok(out.into_inner())
+}
+
+/// Shapes drawn by [`render_shape_grid`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GridShape {
+    Circle,
+    Square,
+    Triangle,
+    Star,
+}
+
+impl GridShape {
+    pub const ALL: [GridShape; 4] = [
+        GridShape::Circle,
+        GridShape::Square,
+        GridShape::Triangle,
+        GridShape::Star,
+    ];
+
+    /// Whether the point `(u, v)` (tile-local, -1..1) lies inside the shape.
+    fn contains(self, u: f32, v: f32) -> bool {
+        match self {
+            GridShape::Circle => u * u + v * v <= 0.62 * 0.62,
+            GridShape::Square => u.abs() <= 0.52 && v.abs() <= 0.52,
+            GridShape::Triangle => in_polygon(&[(0.0, -0.72), (0.68, 0.5), (-0.68, 0.5)], u, v),
+            GridShape::Star => {
+                let points: Vec<(f32, f32)> = (0..10)
+                    .map(|i| {
+                        let r = if i % 2 == 0 { 0.78 } else { 0.33 };
+                        let a = i as f32 * PI / 5.0 - PI / 2.0;
+                        (r * a.cos(), r * a.sin())
+                    })
+                    .collect();
+                in_polygon(&points, u, v)
+            }
+        }
+    }
+}
+
+/// Edge length of one tile in [`render_shape_grid`].
+const GRID_TILE_PX: u32 = 100;
+const GRID_GAP_PX: u32 = 4;
+
+/// Renders `tiles` (shape and fill colour) as a numbered 3-column grid PNG. Every tile gets
+/// its own speckled background, rotation and size so tiles cannot be matched pixel-wise.
+pub fn render_shape_grid(
+    tiles: &[(GridShape, [u8; 3])],
+    fonts: &CaptchaFonts,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut rng = rand::thread_rng();
+    let palette = PALETTES.choose(&mut rng).ok_or("no captcha palette")?;
+    let columns = 3u32;
+    let rows = (tiles.len() as u32).div_ceil(columns).max(1);
+    let step = GRID_TILE_PX + GRID_GAP_PX;
+    let width = columns * step + GRID_GAP_PX;
+    let height = rows * step + GRID_GAP_PX;
+    let mut img = RgbImage::from_pixel(width, height, Rgb([90, 90, 90]));
+    let font = fonts.fonts.choose(&mut rng).ok_or("no captcha fonts")?;
+    let flat = GlyphWarp {
+        angle: 0.0,
+        stretch: 1.0,
+        amplitude: 0.0,
+        frequency: 0.0,
+        phase: 0.0,
+    };
+
+    for (index, &(shape, fill)) in tiles.iter().enumerate() {
+        let x0 = GRID_GAP_PX + (index as u32 % columns) * step;
+        let y0 = GRID_GAP_PX + (index as u32 / columns) * step;
+        let tile = noisy_background(palette, GRID_TILE_PX, GRID_TILE_PX, &mut rng)?;
+        for (x, y, px) in tile.enumerate_pixels() {
+            img.put_pixel(x0 + x, y0 + y, *px);
+        }
+
+        let (sin, cos) = rng.gen_range(0.0..(2.0 * PI)).sin_cos();
+        let scale = rng.gen_range(0.75..0.95);
+        let (ox, oy) = (rng.gen_range(-0.08..0.08), rng.gen_range(-0.08..0.08));
+        let half = GRID_TILE_PX as f32 / 2.0;
+        for y in 0..GRID_TILE_PX {
+            for x in 0..GRID_TILE_PX {
+                // 2x2 supersampling for smooth edges.
+                let mut hits = 0;
+                for (sx, sy) in [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)] {
+                    let du = (x as f32 + sx - half) / half / scale - ox;
+                    let dv = (y as f32 + sy - half) / half / scale - oy;
+                    if shape.contains(du * cos + dv * sin, -du * sin + dv * cos) {
+                        hits += 1;
+                    }
+                }
+                if hits > 0 {
+                    blend(&mut img, x0 + x, y0 + y, fill, hits as f32 / 4.0);
+                }
+            }
+        }
+
+        let label = char::from_digit(index as u32 + 1, 10).unwrap_or('?');
+        let center = (x0 as f32 + 12.0, y0 as f32 + 14.0);
+        draw_glyph(&mut img, font, label, 22.0, center, &flat, [20, 20, 20]);
+    }
+
+    let mut out = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(img).write_to(&mut out, ImageOutputFormat::Png)?;
+    Ok(out.into_inner())
+}
+
+/// Even-odd point-in-polygon test.
+fn in_polygon(points: &[(f32, f32)], u: f32, v: f32) -> bool {
+    let mut inside = false;
+    let mut j = points.len() - 1;
+    for i in 0..points.len() {
+        let (xi, yi) = points[i];
+        let (xj, yj) = points[j];
+        if (yi > v) != (yj > v) && u < (xj - xi) * (v - yi) / (yj - yi) + xi {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Frames each glyph stays on screen for in [`render_captcha_gif`].
+const GIF_FRAMES_PER_GLYPH: usize = 3;
+const GIF_FRAME_DELAY_MS: u32 = 260;
+const GIF_PAUSE_DELAY_MS: u32 = 700;
+
+/// Renders `code` as a looping GIF of exactly `width` x `height` pixels. Glyphs appear one
+/// at a time, left to right, drifting through fresh noise every frame; a noise-only frame
+/// marks the start of each loop. No frame shows more than one glyph.
+pub fn render_captcha_gif(
+    code: &str,
+    fonts: &CaptchaFonts,
+    width: u32,
+    height: u32,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut rng = rand::thread_rng();
+    let palette = PALETTES.choose(&mut rng).ok_or("no captcha palette")?;
+    let glyphs = layout_glyphs(
+        code,
+        fonts,
+        palette,
+        width,
+        height,
+        CODE_STYLE.tilt,
+        &mut rng,
+    )?;
+
+    let mut frames = Vec::with_capacity(glyphs.len() * GIF_FRAMES_PER_GLYPH + 1);
+    let pause = noisy_background(palette, width, height, &mut rng)?;
+    frames.push(gif_frame(pause, GIF_PAUSE_DELAY_MS));
+    for glyph in &glyphs {
+        let drift = rng.gen_range(-0.08..0.08) * height as f32;
+        for step in 0..GIF_FRAMES_PER_GLYPH {
+            let mut img = noisy_background(palette, width, height, &mut rng)?;
+            let t = step as f32 / (GIF_FRAMES_PER_GLYPH - 1).max(1) as f32 - 0.5;
+            glyph.draw(&mut img, drift * t);
+            draw_waves(&mut img, palette, &mut rng)?;
+            frames.push(gif_frame(img, GIF_FRAME_DELAY_MS));
+        }
+    }
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut out, 20);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    Ok(out)
+}
+
+fn gif_frame(img: RgbImage, delay_ms: u32) -> Frame {
+    let rgba = DynamicImage::ImageRgb8(img).into_rgba8();
+    Frame::from_parts(rgba, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+}
+
+/// Background speckles plus the interference lines drawn behind the glyphs.
+fn noisy_background(
+    palette: &Palette,
+    width: u32,
+    height: u32,
+    rng: &mut impl Rng,
+) -> Result<RgbImage, Box<dyn Error + Send + Sync>> {
+    let mut img = RgbImage::from_pixel(width, height, Rgb(palette.background));
+    for _ in 0..(width * height / 10) {
+        let x = rng.gen_range(0..width);
+        let y = rng.gen_range(0..height);
+        let shade: i16 = rng.gen_range(-45..=15);
+        let px = img.get_pixel(x, y).0;
+        img.put_pixel(
+            x,
+            y,
+            Rgb(px.map(|c| (c as i16 + shade).clamp(0, 255) as u8)),
+        );
+    }
+    draw_waves(&mut img, palette, rng)?;
+    Ok(img)
+}
+
+fn draw_waves(
+    img: &mut RgbImage,
+    palette: &Palette,
+    rng: &mut impl Rng,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    for _ in 0..2 {
+        let ink = *palette.inks.choose(rng).ok_or("empty palette")?;
+        draw_wave(img, ink, rng);
+    }
+    Ok(())
+}
+
+/// Font, ink, position and distortion picked for one glyph.
+struct PlacedGlyph<'a> {
+    font: &'a FontArc,
+    ch: char,
+    px_size: f32,
+    center: (f32, f32),
+    warp: GlyphWarp,
+    ink: [u8; 3],
+}
+
+impl PlacedGlyph<'_> {
+    fn draw(&self, img: &mut RgbImage, dy: f32) {
+        let (cx, cy) = self.center;
+        draw_glyph(
+            img,
+            self.font,
+            self.ch,
+            self.px_size,
+            (cx, cy + dy),
+            &self.warp,
+            self.ink,
+        );
+    }
+}
+
+/// Places the glyphs of `code` across the image, each rotated by up to `tilt` radians.
+/// Glyphs use a font that covers them when there is one.
+fn layout_glyphs<'a>(
+    code: &str,
+    fonts: &'a CaptchaFonts,
+    palette: &Palette,
+    width: u32,
+    height: u32,
+    tilt: f32,
+    rng: &mut impl Rng,
+) -> Result<Vec<PlacedGlyph<'a>>, Box<dyn Error + Send + Sync>> {
+    let chars: Vec<char> = code.chars().collect();
+    let count = chars.len().max(1) as f32;
+    let cell = width as f32 * 0.9 / count;
+    let px_size = (height as f32 * 0.62).min(cell * 1.35);
+    let mut cx = (width as f32 - cell * count) / 2.0 + cell / 2.0;
+    let mut glyphs = Vec::with_capacity(chars.len());
+    for ch in chars {
+        let covering: Vec<&FontArc> = fonts
+            .fonts
+            .iter()
+            .filter(|font| font.glyph_id(ch).0 != 0)
+            .collect();
+        let font = match covering.choose(rng) {
+            Some(font) => *font,
+            None => fonts.fonts.choose(rng).ok_or("no captcha fonts")?,
+        };
+        let ink = *palette.inks.choose(rng).ok_or("empty palette")?;
+        let warp = GlyphWarp {
+            angle: rng.gen_range(-tilt..tilt),
+            stretch: rng.gen_range(0.85..1.15),
+            amplitude: px_size * rng.gen_range(0.03..0.08),
+            frequency: rng.gen_range(1.5..3.5) * PI / px_size,
+            phase: rng.gen_range(0.0..(2.0 * PI)),
+        };
+        let cy = height as f32 / 2.0 + rng.gen_range(-0.12..0.12) * height as f32;
+        let jitter = rng.gen_range(-0.1..0.1) * cell;
+        glyphs.push(PlacedGlyph {
+            font,
+            ch,
+            px_size: px_size * rng.gen_range(0.9..1.1),
+            center: (cx + jitter, cy),
+            warp,
+            ink,
+        });
+        cx += cell;
+    }
+    Ok(glyphs)
+}
+
+struct GlyphWarp {
+    angle: f32,
+    stretch: f32,
+    amplitude: f32,
+    frequency: f32,
+    phase: f32,
+}
+
+/// Rasterises one glyph into a coverage buffer, then maps it onto the image through a
+/// rotation, horizontal stretch and sine warp.
+fn draw_glyph(
+    img: &mut RgbImage,
+    font: &FontArc,
+    ch: char,
+    px_size: f32,
+    center: (f32, f32),
+    warp: &GlyphWarp,
+    ink: [u8; 3],
+) {
+    let scaled = font.as_scaled(PxScale::from(px_size));
+    let glyph = scaled
+        .glyph_id(ch)
+        .with_scale_and_position(px_size, point(0.0, scaled.ascent()));
+    let Some(outlined) = font.outline_glyph(glyph) else {
+        return;
+    };
+    let bounds = outlined.px_bounds();
+    let (gw, gh) = (bounds.width() as usize, bounds.height() as usize);
+    if gw == 0 || gh == 0 {
+        return;
+    }
+    let mut coverage = vec![0f32; gw * gh];
+    outlined.draw(|x, y, c| {
+        if let Some(cell) = coverage.get_mut(y as usize * gw + x as usize) {
+            *cell = c;
+        }
+    });
+
+    let (sin, cos) = warp.angle.sin_cos();
+    let radius = (gw.max(gh) as f32) * 0.8 + warp.amplitude;
+    let (cx, cy) = center;
+    let x0 = (cx - radius).floor().max(0.0) as u32;
+    let x1 = ((cx + radius).ceil() as u32).min(img.width());
+    let y0 = (cy - radius).floor().max(0.0) as u32;
+    let y1 = ((cy + radius).ceil() as u32).min(img.height());
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let dx = x as f32 - cx;
+            let dy = y as f32 - cy + warp.amplitude * (dx * warp.frequency + warp.phase).sin();
+            let u = (dx * cos + dy * sin) / warp.stretch + gw as f32 / 2.0;
+            let v = -dx * sin + dy * cos + gh as f32 / 2.0;
+            let alpha = sample(&coverage, gw, gh, u, v);
+            if alpha > 0.0 {
+                blend(img, x, y, ink, alpha);
+            }
+        }
+    }
+}
+
+/// Bilinear lookup into a coverage buffer; outside is transparent.
+fn sample(buf: &[f32], w: usize, h: usize, u: f32, v: f32) -> f32 {
+    if u < 0.0 || v < 0.0 || u >= (w - 1) as f32 || v >= (h - 1) as f32 {
+        return 0.0;
+    }
+    let (x, y) = (u as usize, v as usize);
+    let (fx, fy) = (u - x as f32, v - y as f32);
+    let at = |x: usize, y: usize| buf[y * w + x];
+    let top = at(x, y) * (1.0 - fx) + at(x + 1, y) * fx;
+    let bottom = at(x, y + 1) * (1.0 - fx) + at(x + 1, y + 1) * fx;
+    (top * (1.0 - fy) + bottom * fy).min(1.0)
+}
+
+fn blend(img: &mut RgbImage, x: u32, y: u32, ink: [u8; 3], alpha: f32) {
+    let px = img.get_pixel_mut(x, y);
+    for (dst, src) in px.0.iter_mut().zip(ink) {
+        *dst = (*dst as f32 * (1.0 - alpha) + src as f32 * alpha).round() as u8;
+    }
+}
+
+/// Sine-shaped interference line across the full width.
+fn draw_wave(img: &mut RgbImage, ink: [u8; 3], rng: &mut impl Rng) {
+    let (width, height) = (img.width() as f32, img.height() as f32);
+    let base = rng.gen_range(0.2..0.8) * height;
+    let slope = rng.gen_range(-0.3..0.3) * height / width;
+    let amplitude = rng.gen_range(0.05..0.2) * height;
+    let frequency = rng.gen_range(1.0..3.0) * 2.0 * PI / width;
+    let phase = rng.gen_range(0.0..(2.0 * PI));
+    let thickness = (height / 60.0).max(1.0);
+    for x in 0..img.width() {
+        let fx = x as f32;
+        let y = base + slope * (fx - width / 2.0) + amplitude * (fx * frequency + phase).sin();
+        let mut dy = -thickness;
+        while dy <= thickness {
+            let py = (y + dy).round();
+            if py >= 0.0 && py < height {
+                blend(img, x, py as u32, ink, 0.85);
+            }
+            dy += 1.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_captcha_png_uses_requested_size() {
+        let fonts = CaptchaFonts::default();
+        assert_eq!(fonts.len(), BUNDLED_FONTS.len());
+        let png = render_captcha_png("AB34XY", &fonts, 960, 300).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!((img.width(), img.height()), (960, 300));
+    }
+
+    #[test]
+    fn render_captcha_gif_has_frame_per_glyph_step() {
+        use image::AnimationDecoder;
+        use image::codecs::gif::GifDecoder;
+
+        let fonts = CaptchaFonts::default();
+        let gif = render_captcha_gif("AB34", &fonts, 320, 100).unwrap();
+        assert!(gif.starts_with(b"GIF89a"));
+        let frames = GifDecoder::new(Cursor::new(gif))
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(frames.len(), 4 * GIF_FRAMES_PER_GLYPH + 1);
+        assert_eq!(frames[0].buffer().dimensions(), (320, 100));
+    }
+
+    #[test]
+    fn render_shape_grid_lays_out_three_columns() {
+        let tiles: Vec<(GridShape, [u8; 3])> = GridShape::ALL
+            .iter()
+            .cycle()
+            .take(9)
+            .map(|&shape| (shape, [200, 40, 40]))
+            .collect();
+        let png = render_shape_grid(&tiles, &CaptchaFonts::default()).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        let side = 3 * (GRID_TILE_PX + GRID_GAP_PX) + GRID_GAP_PX;
+        assert_eq!((img.width(), img.height()), (side, side));
+        assert!(GridShape::Star.contains(0.0, -0.7));
+        assert!(!GridShape::Star.contains(0.5, -0.5));
+    }
+}
@@ -0,0 +1,177 @@
+use std::error::Error;
+
+use rusqlite::params;
+use teloxide::types::{ChatId, User, UserId};
+
+use crate::ban_release::open_db;
+use crate::captcha::PendingCaptcha;
+
+#[derive(Clone)]
+pub struct CaptchaStore {
+    db_path: String,
+}
+
+impl CaptchaStore {
+    pub async fn init(db_path: String) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let store = Self { db_path };
+        let path = store.db_path.clone();
+        tokio::task::spawn_blocking(move || init_db(&path))
+            .await?
+            .map_err(|err| -> Box<dyn Error + Send + Sync> { err.into() })?;
+        Ok(store)
+    }
+
+    pub async fn upsert_pending(
+        &self,
+        chat_id: ChatId,
+        user: &User,
+        pending: &PendingCaptcha,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = self.db_path.clone();
+        let user_id = i64::try_from(user.id.0).map_err(|_| "user id out of range")?;
+        let expires_at = pending.expires_at;
+        let user_json = serde_json::to_string(user)?;
+        let pending_json = serde_json::to_string(pending)?;
+        tokio::task::spawn_blocking(move || {
+            let conn = open_db(&path)?;
+            conn.execute(
+                "INSERT INTO pending_captchas
+                 (chat_id, user_id, expires_at, user_json, pending_json)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(chat_id, user_id) DO UPDATE SET
+                    expires_at=excluded.expires_at,
+                    user_json=excluded.user_json,
+                    pending_json=excluded.pending_json",
+                params![chat_id.0, user_id, expires_at, user_json, pending_json],
+            )?;
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await?
+        .map_err(|err| err.into())
+    }
+
+    pub async fn fetch_pending(&self) -> Result<Vec<StoredCaptcha>, Box<dyn Error + Send + Sync>> {
+        let path = self.db_path.clone();
+        let rows = tokio::task::spawn_blocking(move || {
+            let conn = open_db(&path)?;
+            let mut stmt = conn.prepare(
+                "SELECT chat_id, user_id, user_json, pending_json
+                 FROM pending_captchas
+                 ORDER BY expires_at ASC",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            })?;
+            let mut out = Vec::new();
+            for row in rows {
+                out.push(row?);
+            }
+            Ok::<_, rusqlite::Error>(out)
+        })
+        .await??;
+
+        let mut out = Vec::with_capacity(rows.len());
+        for (chat_id, user_id, user_json, pending_json) in rows {
+            let decoded = serde_json::from_str::<User>(&user_json).and_then(|user| {
+                serde_json::from_str::<PendingCaptcha>(&pending_json).map(|p| (user, p))
+            });
+            match decoded {
+                Ok((user, pending)) => out.push(StoredCaptcha {
+                    chat_id: ChatId(chat_id),
+                    user,
+                    pending,
+                }),
+                Err(_) => {
+                    // Rows written by an incompatible version cannot be resumed; drop them so
+                    // they do not linger forever.
+                    self.delete_pending(ChatId(chat_id), UserId(user_id as u64))
+                        .await?;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    pub async fn delete_pending(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = self.db_path.clone();
+        let user_id = i64::try_from(user_id.0).map_err(|_| "user id out of range")?;
+        tokio::task::spawn_blocking(move || {
+            let conn = open_db(&path)?;
+            conn.execute(
+                "DELETE FROM pending_captchas WHERE chat_id = ?1 AND user_id = ?2",
+                params![chat_id.0, user_id],
+            )?;
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await?
+        .map_err(|err| err.into())
+    }
+}
+
+fn init_db(path: &str) -> Result<(), rusqlite::Error> {
+    let conn = open_db(path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS pending_captchas (
+            chat_id INTEGER NOT NULL,
+            user_id INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            user_json TEXT NOT NULL,
+            pending_json TEXT NOT NULL,
+            PRIMARY KEY (chat_id, user_id)
+        );",
+    )?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct StoredCaptcha {
+    pub chat_id: ChatId,
+    pub user: User,
+    pub pending: PendingCaptcha,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::captcha::{test_pending, test_user};
+    use teloxide::types::MessageId;
+
+    #[tokio::test]
+    async fn pending_captcha_roundtrip() {
+        let path = std::env::temp_dir().join(format!(
+            "buktikan-captcha-store-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let store = CaptchaStore::init(path.to_string_lossy().to_string())
+            .await
+            .unwrap();
+        let user = test_user();
+        let pending = test_pending("image", "ABC123");
+        store
+            .upsert_pending(ChatId(-100), &user, &pending)
+            .await
+            .unwrap();
+
+        let stored = store.fetch_pending().await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].chat_id, ChatId(-100));
+        assert_eq!(stored[0].user.id, user.id);
+        assert_eq!(stored[0].pending.code, "ABC123");
+        assert_eq!(stored[0].pending.captcha_message_id, MessageId(10));
+        assert_eq!(stored[0].pending.expires_at, pending.expires_at);
+
+        store.delete_pending(ChatId(-100), user.id).await.unwrap();
+        assert!(store.fetch_pending().await.unwrap().is_empty());
+        let _ = std::fs::remove_file(&path);
+    }
+}
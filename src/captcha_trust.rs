@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use rusqlite::params;
+use teloxide::types::{ChatId, UserId};
+
+use crate::ban_release::open_db;
+
+/// Scope used when no named chat groups are configured: trust is shared by every chat.
+const GLOBAL_SCOPE: &str = "*";
+
+/// What a user who passed the captcha in a related chat gets on joining.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrustAction {
+    /// No restriction and no captcha.
+    Skip,
+    /// The one-tap emoji captcha instead of the chat's usual challenge.
+    Light,
+}
+
+/// Users verified in one chat, remembered in SQLite for a while so related chats can let
+/// them in without the full captcha. Any failed captcha revokes the user's trust everywhere.
+#[derive(Debug)]
+pub struct CaptchaTrust {
+    db_path: String,
+    ttl_secs: u64,
+    action: TrustAction,
+    /// Named chat groups sharing trust; empty shares it across all chats.
+    scopes: HashMap<ChatId, String>,
+}
+
+impl CaptchaTrust {
+    pub fn new(
+        db_path: String,
+        ttl_secs: u64,
+        action: TrustAction,
+        scopes: HashMap<ChatId, String>,
+    ) -> Self {
+        Self {
+            db_path,
+            ttl_secs,
+            action,
+            scopes,
+        }
+    }
+
+    pub fn action(&self) -> TrustAction {
+        self.action
+    }
+
+    pub fn scope_count(&self) -> usize {
+        let mut names: Vec<&String> = self.scopes.values().collect();
+        names.sort_unstable();
+        names.dedup();
+        names.len()
+    }
+
+    /// Trust scope of a chat. `None` for chats outside every named group, which neither
+    /// grant nor use trust.
+    fn scope(&self, chat_id: ChatId) -> Option<String> {
+        if self.scopes.is_empty() {
+            return Some(GLOBAL_SCOPE.to_string());
+        }
+        self.scopes.get(&chat_id).cloned()
+    }
+
+    pub async fn init(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = self.db_path.clone();
+        tokio::task::spawn_blocking(move || init_db(&path))
+            .await?
+            .map_err(|err| err.into())
+    }
+
+    /// Whether `user_id` passed a captcha in a chat sharing `chat_id`'s scope and the
+    /// trust has not expired.
+    pub async fn is_trusted(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+        now_ts: i64,
+    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        let Some(scope) = self.scope(chat_id) else {
+            return Ok(false);
+        };
+        let path = self.db_path.clone();
+        let user_id = i64::try_from(user_id.0).map_err(|_| "user id out of range")?;
+        tokio::task::spawn_blocking(move || {
+            let conn = open_db(&path)?;
+            let found: i64 = conn.query_row(
+                "SELECT COUNT(*) FROM verified_users
+                 WHERE user_id = ?1 AND scope = ?2 AND expires_at > ?3",
+                params![user_id, scope, now_ts],
+                |row| row.get(0),
+            )?;
+            Ok::<_, rusqlite::Error>(found > 0)
+        })
+        .await?
+        .map_err(|err| err.into())
+    }
+
+    /// Remembers a passed captcha in `chat_id`, renewing the trust of its scope.
+    pub async fn record(
+        &self,
+        chat_id: ChatId,
+        user_id: UserId,
+        now_ts: i64,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let Some(scope) = self.scope(chat_id) else {
+            return Ok(());
+        };
+        let path = self.db_path.clone();
+        let user_id = i64::try_from(user_id.0).map_err(|_| "user id out of range")?;
+        let expires_at = now_ts + self.ttl_secs as i64;
+        tokio::task::spawn_blocking(move || {
+            let conn = open_db(&path)?;
+            conn.execute(
+                "INSERT INTO verified_users (user_id, scope, chat_id, verified_at, expires_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5)
+                 ON CONFLICT(user_id, scope) DO UPDATE SET
+                    chat_id=excluded.chat_id,
+                    verified_at=excluded.verified_at,
+                    expires_at=excluded.expires_at",
+                params![user_id, scope, chat_id.0, now_ts, expires_at],
+            )?;
+            conn.execute(
+                "DELETE FROM verified_users WHERE expires_at <= ?1",
+                params![now_ts],
+            )?;
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await?
+        .map_err(|err| err.into())
+    }
+
+    /// Drops the user's trust in every scope.
+    pub async fn revoke(&self, user_id: UserId) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = self.db_path.clone();
+        let user_id = i64::try_from(user_id.0).map_err(|_| "user id out of range")?;
+        tokio::task::spawn_blocking(move || {
+            let conn = open_db(&path)?;
+            conn.execute(
+                "DELETE FROM verified_users WHERE user_id = ?1",
+                params![user_id],
+            )?;
+            Ok::<_, rusqlite::Error>(())
+        })
+        .await?
+        .map_err(|err| err.into())
+    }
+}
+
+fn init_db(path: &str) -> Result<(), rusqlite::Error> {
+    let conn = open_db(path)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS verified_users (
+            user_id INTEGER NOT NULL,
+            scope TEXT NOT NULL,
+            chat_id INTEGER NOT NULL,
+            verified_at INTEGER NOT NULL,
+            expires_at INTEGER NOT NULL,
+            PRIMARY KEY (user_id, scope)
+        );",
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn trust_is_scoped_expires_and_revokes() {
+        let path = std::env::temp_dir().join(format!(
+            "buktikan-captcha-trust-{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let scopes = HashMap::from([
+            (ChatId(-1), "network".to_string()),
+            (ChatId(-2), "network".to_string()),
+            (ChatId(-3), "other".to_string()),
+        ]);
+        let trust = CaptchaTrust::new(
+            path.to_string_lossy().to_string(),
+            3600,
+            TrustAction::Skip,
+            scopes,
+        );
+        trust.init().await.unwrap();
+        assert_eq!(trust.scope_count(), 2);
+
+        let user = UserId(42);
+        trust.record(ChatId(-1), user, 1_000).await.unwrap();
+        assert!(trust.is_trusted(ChatId(-2), user, 1_100).await.unwrap());
+        assert!(!trust.is_trusted(ChatId(-3), user, 1_100).await.unwrap());
+        assert!(!trust.is_trusted(ChatId(-9), user, 1_100).await.unwrap());
+        assert!(!trust.is_trusted(ChatId(-2), user, 4_600).await.unwrap());
+
+        trust.record(ChatId(-3), user, 2_000).await.unwrap();
+        trust.revoke(user).await.unwrap();
+        assert!(!trust.is_trusted(ChatId(-1), user, 2_100).await.unwrap());
+        assert!(!trust.is_trusted(ChatId(-3), user, 2_100).await.unwrap());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Html;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rand::distributions::Alphanumeric;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use teloxide::types::{ChatId, InlineKeyboardMarkup, UserId};
+use url::Url;
+
+use crate::captcha::{
+    CaptchaChallenge, CaptchaMessageKind, ChallengeProvider, PendingCaptcha, PrivateStart,
+};
+use crate::captcha_private::verify_start_keyboard;
+use crate::config::{Config, LogLevel};
+use crate::handlers::{CaptchaContext, WebAppAnswer, answer_webapp_captcha};
+use crate::logging::log_system_level;
+
+/// Path of the Mini App page on the webhook server; answers are posted to `<path>/verify`.
+pub const CAPTCHA_WEBAPP_PATH: &str = "/captcha/webapp";
+
+/// `initData` older than this is rejected even with a valid signature.
+const INIT_DATA_MAX_AGE_SECS: i64 = 3600;
+
+const WEBAPP_PAGE: &str = include_str!("captcha_webapp.html");
+
+/// Mini App settings, available in webhook mode only.
+#[derive(Debug)]
+pub struct CaptchaWebApp {
+    page_url: Url,
+    pow_bits: u32,
+}
+
+impl CaptchaWebApp {
+    pub fn new(page_url: Url, pow_bits: u32) -> Self {
+        Self { page_url, pow_bits }
+    }
+
+    pub fn pow_bits(&self) -> u32 {
+        self.pow_bits
+    }
+
+    /// Mini App page for one session. The seed and difficulty are not secret: the work
+    /// only slows down mass solving, the signed `initData` proves who solved it.
+    pub fn page_url(&self, chat_id: ChatId, pending: &PendingCaptcha) -> Url {
+        let mut url = self.page_url.clone();
+        url.query_pairs_mut()
+            .append_pair("chat", &chat_id.0.to_string())
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("seed", &pending.code)
+            .append_pair("bits", &pending_pow_bits(pending).to_string());
+        url
+    }
+}
+
+/// Verification inside a Telegram Mini App: the group message links to the bot's private
+/// chat (Telegram only allows Web App buttons there), which opens a page doing a small
+/// proof-of-work. The result is posted back with the signed `initData`.
+pub struct WebAppChallenge;
+
+impl ChallengeProvider for WebAppChallenge {
+    fn name(&self) -> &'static str {
+        "webapp"
+    }
+
+    fn aliases(&self) -> &'static [&'static str] {
+        &["miniapp"]
+    }
+
+    fn message_kind(&self) -> CaptchaMessageKind {
+        CaptchaMessageKind::Text
+    }
+
+    fn available(&self, config: &Config, _chat_id: ChatId) -> bool {
+        config.captcha_webapp.is_some() && config.bot_username.get().is_some()
+    }
+
+    fn generate(
+        &self,
+        config: &Config,
+        _chat_id: ChatId,
+    ) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+        let webapp = config
+            .captcha_webapp
+            .as_ref()
+            .ok_or("captcha web app is not configured")?;
+        let seed: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect();
+        Ok(CaptchaChallenge {
+            code: seed,
+            media: None,
+            options: Vec::new(),
+            prompt: None,
+            data: serde_json::json!({ "bits": webapp.pow_bits() }),
+        })
+    }
+
+    fn instruction(&self, _prompt: Option<&str>) -> String {
+        "🧩 Tekan <b>Verifikasi</b>, lalu <b>Start</b> di chat bot dan selesaikan verifikasi di halaman yang terbuka."
+            .to_string()
+    }
+
+    fn keyboard(
+        &self,
+        _options: &[String],
+        config: &Config,
+        nonce: &str,
+        _refresh: bool,
+    ) -> InlineKeyboardMarkup {
+        verify_start_keyboard(config, "🧩 Verifikasi", nonce)
+    }
+
+    /// The answer is the proof-of-work counter found by the page.
+    fn check_answer(&self, pending: &PendingCaptcha, answer: &str) -> bool {
+        pow_solves(&pending.code, answer, pending_pow_bits(pending))
+    }
+
+    fn private_start(&self) -> Option<PrivateStart> {
+        Some(PrivateStart::WebAppButton)
+    }
+}
+
+fn pending_pow_bits(pending: &PendingCaptcha) -> u32 {
+    pending
+        .provider_data
+        .get("bits")
+        .and_then(serde_json::Value::as_u64)
+        .and_then(|bits| u32::try_from(bits).ok())
+        .unwrap_or(16)
+}
+
+/// Whether SHA-256 of `<seed>:<counter>` starts with at least `bits` zero bits.
+fn pow_solves(seed: &str, counter: &str, bits: u32) -> bool {
+    if counter.is_empty() || counter.len() > 20 || !counter.bytes().all(|b| b.is_ascii_digit()) {
+        return false;
+    }
+    let hash = Sha256::digest(format!("{seed}:{counter}").as_bytes());
+    leading_zero_bits(&hash) >= bits
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in bytes {
+        if *byte == 0 {
+            bits += 8;
+        } else {
+            return bits + byte.leading_zeros();
+        }
+    }
+    bits
+}
+
+/// The `user` object of Mini App `initData`.
+#[derive(Debug, Deserialize)]
+pub struct WebAppUser {
+    pub id: u64,
+    pub first_name: String,
+    #[serde(default)]
+    pub last_name: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub language_code: Option<String>,
+    #[serde(default)]
+    pub is_premium: bool,
+}
+
+impl WebAppUser {
+    fn into_user(self) -> teloxide::types::User {
+        teloxide::types::User {
+            id: UserId(self.id),
+            is_bot: false,
+            first_name: self.first_name,
+            last_name: self.last_name,
+            username: self.username,
+            language_code: self.language_code,
+            is_premium: self.is_premium,
+            added_to_attachment_menu: false,
+        }
+    }
+}
+
+/// Checks the `hash` of Mini App `initData` against the bot token as described in
+/// Telegram's "Validating data received via the Mini App", and returns the user.
+pub fn validate_init_data(
+    token: &str,
+    init_data: &str,
+    now: i64,
+) -> Result<WebAppUser, &'static str> {
+    let mut fields: BTreeMap<String, String> = BTreeMap::new();
+    for (key, value) in url::form_urlencoded::parse(init_data.as_bytes()) {
+        fields.insert(key.into_owned(), value.into_owned());
+    }
+    let hash = fields.remove("hash").ok_or("missing hash")?;
+    let hash = decode_hex(&hash).ok_or("malformed hash")?;
+    let check_string = fields
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut secret = Hmac::<Sha256>::new_from_slice(b"WebAppData").expect("any key length");
+    secret.update(token.as_bytes());
+    let secret = secret.finalize().into_bytes();
+    let mut mac = Hmac::<Sha256>::new_from_slice(&secret).expect("any key length");
+    mac.update(check_string.as_bytes());
+    mac.verify_slice(&hash).map_err(|_| "bad signature")?;
+
+    let auth_date: i64 = fields
+        .get("auth_date")
+        .and_then(|raw| raw.parse().ok())
+        .ok_or("missing auth_date")?;
+    if now - auth_date > INIT_DATA_MAX_AGE_SECS || auth_date - now > 60 {
+        return Err("expired");
+    }
+    let user = fields.get("user").ok_or("missing user")?;
+    serde_json::from_str(user).map_err(|_| "malformed user")
+}
+
+fn decode_hex(raw: &str) -> Option<Vec<u8>> {
+    if !raw.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..raw.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(raw.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Routes for the Mini App page and its answer endpoint, to be merged into the webhook
+/// server.
+pub fn router(context: CaptchaContext) -> Router {
+    Router::new()
+        .route(CAPTCHA_WEBAPP_PATH, get(webapp_page))
+        .route(
+            &format!("{CAPTCHA_WEBAPP_PATH}/verify"),
+            post(webapp_verify),
+        )
+        .with_state(context)
+}
+
+async fn webapp_page() -> Html<&'static str> {
+    Html(WEBAPP_PAGE)
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyRequest {
+    init_data: String,
+    chat_id: i64,
+    nonce: String,
+    counter: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyResponse {
+    ok: bool,
+    message: &'static str,
+}
+
+fn reply(
+    status: StatusCode,
+    ok: bool,
+    message: &'static str,
+) -> (StatusCode, Json<VerifyResponse>) {
+    (status, Json(VerifyResponse { ok, message }))
+}
+
+async fn webapp_verify(
+    State(context): State<CaptchaContext>,
+    Json(request): Json<VerifyRequest>,
+) -> (StatusCode, Json<VerifyResponse>) {
+    let user = match validate_init_data(
+        &context.config.token,
+        &request.init_data,
+        Utc::now().timestamp(),
+    ) {
+        Ok(user) => user.into_user(),
+        Err(reason) => {
+            log_system_level(
+                &context.config,
+                LogLevel::Warn,
+                &format!("captcha web app: rejected initData ({reason})"),
+            );
+            return reply(
+                StatusCode::UNAUTHORIZED,
+                false,
+                "Data Telegram tidak valid. Buka ulang halaman ini dari chat bot.",
+            );
+        }
+    };
+    let chat_id = ChatId(request.chat_id);
+    let current = {
+        let guard = context.state.lock().await;
+        guard.get(&(chat_id, user.id)).is_some_and(|pending| {
+            pending.nonce == request.nonce && pending.provider == WebAppChallenge.name()
+        })
+    };
+    if !current {
+        return reply(
+            StatusCode::NOT_FOUND,
+            false,
+            "Sesi verifikasi tidak ditemukan atau sudah berakhir.",
+        );
+    }
+
+    let outcome = answer_webapp_captcha(&context, chat_id, &user, &request.counter).await;
+    match outcome {
+        WebAppAnswer::Verified => reply(
+            StatusCode::OK,
+            true,
+            "✅ Verifikasi berhasil. Silakan kembali ke grup.",
+        ),
+        WebAppAnswer::Retry => reply(
+            StatusCode::OK,
+            false,
+            "❌ Verifikasi gagal. Tekan tombol Verifikasi di grup untuk mencoba lagi.",
+        ),
+        WebAppAnswer::Banned => reply(
+            StatusCode::OK,
+            false,
+            "❌ Kesempatan habis. Kamu dikeluarkan.",
+        ),
+        WebAppAnswer::NoPending => reply(
+            StatusCode::NOT_FOUND,
+            false,
+            "Sesi verifikasi tidak ditemukan atau sudah berakhir.",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(token: &str, fields: &[(&str, &str)]) -> String {
+        let mut sorted = fields.to_vec();
+        sorted.sort();
+        let check_string = sorted
+            .iter()
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let mut secret = Hmac::<Sha256>::new_from_slice(b"WebAppData").unwrap();
+        secret.update(token.as_bytes());
+        let mut mac = Hmac::<Sha256>::new_from_slice(&secret.finalize().into_bytes()).unwrap();
+        mac.update(check_string.as_bytes());
+        let hash: String = mac
+            .finalize()
+            .into_bytes()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let mut serializer = url::form_urlencoded::Serializer::new(String::new());
+        for (key, value) in fields {
+            serializer.append_pair(key, value);
+        }
+        serializer.append_pair("hash", &hash);
+        serializer.finish()
+    }
+
+    #[test]
+    fn validate_init_data_checks_signature_and_age() {
+        let user = r#"{"id":42,"first_name":"Ani","username":"ani"}"#;
+        let fields = [("auth_date", "1000"), ("query_id", "AAE"), ("user", user)];
+        let init_data = sign("123:abc", &fields);
+
+        let parsed = validate_init_data("123:abc", &init_data, 1_100).unwrap();
+        assert_eq!(parsed.id, 42);
+        assert_eq!(parsed.username.as_deref(), Some("ani"));
+
+        assert_eq!(
+            validate_init_data("123:other", &init_data, 1_100).unwrap_err(),
+            "bad signature"
+        );
+        let tampered = init_data.replace("1000", "1001");
+        assert!(validate_init_data("123:abc", &tampered, 1_100).is_err());
+        assert_eq!(
+            validate_init_data("123:abc", &init_data, 1_000 + INIT_DATA_MAX_AGE_SECS + 1)
+                .unwrap_err(),
+            "expired"
+        );
+    }
+
+    #[test]
+    fn pow_solves_requires_leading_zero_bits() {
+        assert_eq!(leading_zero_bits(&[0, 0x10, 0xff]), 11);
+        let counter = (0u64..)
+            .find(|counter| pow_solves("seed", &counter.to_string(), 8))
+            .unwrap()
+            .to_string();
+        assert!(pow_solves("seed", &counter, 8));
+        assert!(!pow_solves("other", &counter, 24));
+        assert!(!pow_solves("seed", "12a", 0));
+        assert!(!pow_solves("seed", "", 0));
+    }
+}
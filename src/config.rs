@@ -1,11 +1,28 @@
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::error::Error;
 use std::net::{IpAddr, SocketAddr};
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
 
+use chrono::Utc;
 use chrono_tz::Tz;
+use teloxide::types::{ChatId, UserId};
 use url::Url;
 
+use crate::captcha::challenge_provider;
+use crate::captcha_adaptive::{AdaptiveDifficulty, CaptchaDifficulty};
+use crate::captcha_alphabet::CaptchaAlphabet;
+use crate::captcha_approvals::JoinApprovals;
+use crate::captcha_audio::CaptchaVoice;
+use crate::captcha_invite::InvitePolicies;
+use crate::captcha_math::math_option_count;
+use crate::captcha_pool::{CaptchaPool, PoolKey};
+use crate::captcha_questions::QuestionBank;
+use crate::captcha_render::CaptchaFonts;
+use crate::captcha_trust::{CaptchaTrust, TrustAction};
+use crate::captcha_webapp::{CAPTCHA_WEBAPP_PATH, CaptchaWebApp};
 use crate::utils::sanitize_log_text;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -34,19 +51,61 @@ pub enum RunMode {
 #[derive(Clone, Debug)]
 pub struct Config {
     pub token: String,
+    /// Name of the [`ChallengeProvider`](crate::captcha::ChallengeProvider) in use.
+    pub captcha_mode: &'static str,
+    pub captcha_question_bank: Option<QuestionBank>,
     pub captcha_len: usize,
+    /// Characters image, animated and typed codes are drawn from, unless the chat has its own.
+    pub captcha_alphabet: CaptchaAlphabet,
+    pub captcha_chat_alphabets: HashMap<ChatId, CaptchaAlphabet>,
+    pub captcha_case_sensitive: bool,
     pub captcha_timeout_secs: u64,
     pub captcha_caption_update_secs: u64,
     pub captcha_width: u32,
     pub captcha_height: u32,
+    pub captcha_fonts: CaptchaFonts,
+    /// Image captchas rendered ahead of time; `None` renders every captcha on demand.
+    pub captcha_pool: Option<Arc<CaptchaPool>>,
+    /// Voice samples for the audio captcha; `None` hides the "🔊 Audio" button.
+    pub captcha_voice: Option<Arc<CaptchaVoice>>,
     pub captcha_option_count: usize,
     pub captcha_attempts: usize,
+    pub captcha_refresh_limit: usize,
+    /// Answers quicker than this are treated as bots and count as wrong; 0 disables.
+    pub captcha_min_solve_ms: u64,
+    /// Per-chat join/failure tracker that scales difficulty up during spam waves; `None`
+    /// keeps the configured baseline.
+    pub captcha_adaptive: Option<Arc<AdaptiveDifficulty>>,
+    /// Telegram Mini App verification served from the webhook server; `None` outside
+    /// `CAPTCHA_MODE=webapp` in webhook mode.
+    pub captcha_webapp: Option<Arc<CaptchaWebApp>>,
     pub captcha_option_digits_to_emoji: bool,
+    /// Draw answer and keypad labels in random Unicode forms so they do not match OCR output.
+    pub captcha_option_obfuscate: bool,
+    /// Answer join requests with a captcha in the applicant's private chat.
+    pub captcha_join_requests: bool,
+    /// Join requests approved by the bot, set while `captcha_join_requests` is on.
+    pub captcha_join_approvals: Option<Arc<JoinApprovals>>,
+    /// Post only a "🔐 Verifikasi" deep link in the group and run the captcha in the user's
+    /// private chat with the bot.
+    pub captcha_private_verify: bool,
+    /// Skip the captcha for members added by an administrator with invite rights, or by
+    /// one of `captcha_trusted_inviters`.
+    pub captcha_skip_admin_added: bool,
+    pub captcha_trusted_inviters: HashSet<UserId>,
+    /// Users verified in a related chat, kept in SQLite; `None` challenges everyone in
+    /// every chat.
+    pub captcha_trust: Option<Arc<CaptchaTrust>>,
+    /// Policies for members joining through particular invite links.
+    pub captcha_invite_policies: InvitePolicies,
+    /// Filled in from `getMe` at startup; deep links to the bot's private chat need it.
+    pub bot_username: OnceLock<String>,
     pub delete_join_message: bool,
     pub delete_left_message: bool,
     pub ban_release_enabled: bool,
     pub ban_release_after_secs: u64,
     pub ban_release_db_path: String,
+    pub captcha_persist_enabled: bool,
     pub log_enabled: bool,
     pub log_json: bool,
     pub log_level: LogLevel,
@@ -66,17 +125,221 @@ impl Config {
         let token = env::var("BOT_TOKEN")
             .or_else(|_| env::var("TELOXIDE_TOKEN"))
             .map_err(|_| "BOT_TOKEN or TELOXIDE_TOKEN is required")?;
+        let captcha_mode = env::var("CAPTCHA_MODE")
+            .ok()
+            .and_then(|v| {
+                challenge_provider(&v)
+                    .map(|provider| provider.name())
+                    .or_else(|| {
+                        warnings.push(format!(
+                            "CAPTCHA_MODE invalid ('{}'), using image",
+                            sanitize_log_text(&v)
+                        ));
+                        None
+                    })
+            })
+            .unwrap_or("image");
+        let mut captcha_mode = captcha_mode;
+        let captcha_question_bank = env::var("CAPTCHA_QUESTION_BANK_PATH")
+            .ok()
+            .filter(|path| !path.trim().is_empty())
+            .and_then(
+                |path| match QuestionBank::load(path.trim(), &mut warnings) {
+                    Ok(bank) => Some(bank),
+                    Err(err) => {
+                        warnings.push(format!(
+                            "CAPTCHA_QUESTION_BANK_PATH failed to load ('{}'): {}",
+                            sanitize_log_text(&path),
+                            err
+                        ));
+                        None
+                    }
+                },
+            );
+        if captcha_mode == "question"
+            && captcha_question_bank
+                .as_ref()
+                .is_none_or(|bank| bank.is_empty())
+        {
+            captcha_mode = "image";
+            warnings.push(
+                "CAPTCHA_MODE question requires a non-empty CAPTCHA_QUESTION_BANK_PATH; using image"
+                    .to_string(),
+            );
+        }
+        let run_mode = match env::var("RUN_MODE").ok() {
+            Some(raw) => parse_run_mode(&raw).ok_or_else(|| {
+                format!(
+                    "RUN_MODE invalid ('{}'), expected polling|webhook",
+                    sanitize_log_text(&raw)
+                )
+            })?,
+            None => RunMode::Polling,
+        };
+        let webhook_path = normalize_webhook_path(
+            env::var("WEBHOOK_PATH").unwrap_or_else(|_| "/telegram".to_string()),
+        );
+        let webhook_url = env::var("WEBHOOK_URL")
+            .ok()
+            .map(|raw| parse_webhook_url(&raw, &webhook_path))
+            .transpose()?;
+        let webhook_listen_addr = parse_webhook_listen_addr(&mut warnings);
+        let webhook_secret_token = env::var("WEBHOOK_SECRET_TOKEN")
+            .ok()
+            .map(|raw| validate_webhook_secret(&raw))
+            .transpose()?;
+        let captcha_webapp = match (&webhook_url, run_mode) {
+            (Some(url), RunMode::Webhook) if captcha_mode == "webapp" => {
+                let pow_bits = parse_env_u32("CAPTCHA_WEBAPP_POW_BITS", 16, 8, 24, &mut warnings);
+                let page_url = url
+                    .join(CAPTCHA_WEBAPP_PATH)
+                    .map_err(|err| format!("captcha web app URL invalid: {err}"))?;
+                Some(Arc::new(CaptchaWebApp::new(page_url, pow_bits)))
+            }
+            _ => None,
+        };
+        if captcha_mode == "webapp" && captcha_webapp.is_none() {
+            captcha_mode = "image";
+            warnings.push(
+                "CAPTCHA_MODE webapp requires RUN_MODE=webhook with WEBHOOK_URL; using image"
+                    .to_string(),
+            );
+        }
         let captcha_len = parse_env_usize("CAPTCHA_LEN", 6, 4, 12, &mut warnings);
+        let captcha_case_sensitive = parse_env_bool("CAPTCHA_CASE_SENSITIVE", false, &mut warnings);
+        let captcha_alphabet = env::var("CAPTCHA_ALPHABET")
+            .ok()
+            .filter(|raw| !raw.trim().is_empty())
+            .and_then(|raw| {
+                CaptchaAlphabet::parse(&raw, captcha_case_sensitive, &mut warnings)
+                    .map_err(|err| {
+                        warnings.push(format!(
+                            "CAPTCHA_ALPHABET invalid ('{}': {err}), using safe",
+                            sanitize_log_text(&raw)
+                        ));
+                    })
+                    .ok()
+            })
+            .unwrap_or_default();
+        let captcha_chat_alphabets = parse_chat_alphabets(captcha_case_sensitive, &mut warnings);
         let captcha_timeout_secs =
             parse_env_u64("CAPTCHA_TIMEOUT_SECONDS", 120, 30, 600, &mut warnings);
         let captcha_caption_update_secs =
             parse_env_u64("CAPTCHA_CAPTION_UPDATE_SECONDS", 10, 2, 30, &mut warnings);
-        let captcha_width = parse_env_u32("CAPTCHA_WIDTH", 320, 160, 400, &mut warnings);
-        let captcha_height = parse_env_u32("CAPTCHA_HEIGHT", 100, 60, 200, &mut warnings);
+        let captcha_width = parse_env_u32("CAPTCHA_WIDTH", 320, 160, 1600, &mut warnings);
+        let captcha_height = parse_env_u32("CAPTCHA_HEIGHT", 100, 60, 800, &mut warnings);
+        let captcha_font_paths: Vec<String> = env::var("CAPTCHA_FONT_PATHS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|path| path.trim().to_string())
+            .filter(|path| !path.is_empty())
+            .collect();
+        let captcha_fonts = CaptchaFonts::load(&captcha_font_paths, &mut warnings);
+        let captcha_pool_size = parse_env_usize("CAPTCHA_POOL_SIZE", 16, 0, 256, &mut warnings);
+        let alphabets =
+            || std::iter::once(&captcha_alphabet).chain(captcha_chat_alphabets.values());
+        let mut voice_chars: Vec<char> = alphabets()
+            .flat_map(|alphabet| alphabet.chars().iter().map(char::to_ascii_uppercase))
+            .collect();
+        voice_chars.sort_unstable();
+        voice_chars.dedup();
+        let captcha_audio_enabled = parse_env_bool("CAPTCHA_AUDIO_ENABLED", false, &mut warnings);
+        // A voice cannot tell "a" from "A".
+        let has_lowercase = alphabets().any(CaptchaAlphabet::has_lowercase);
+        if captcha_audio_enabled && has_lowercase {
+            warnings.push(
+                "audio captcha disabled: a captcha alphabet has lower-case letters".to_string(),
+            );
+        }
+        let captcha_voice = (captcha_audio_enabled && !has_lowercase)
+            .then(|| {
+                match env::var("CAPTCHA_AUDIO_SAMPLES_DIR")
+                    .ok()
+                    .filter(|dir| !dir.trim().is_empty())
+                {
+                    Some(dir) => {
+                        CaptchaVoice::load(Path::new(dir.trim()), &voice_chars, &mut warnings)
+                    }
+                    None => CaptchaVoice::builtin(&voice_chars, &mut warnings),
+                }
+            })
+            .filter(|voice| !voice.is_empty())
+            .map(Arc::new);
         let captcha_option_count = parse_env_usize("CAPTCHA_OPTION_COUNT", 6, 3, 12, &mut warnings);
+        // Question mode falls back to image captchas in chats without questions.
+        let pool_keys: Vec<PoolKey> = match captcha_mode {
+            "image" | "typed" | "keypad" | "question" => alphabets()
+                .map(|alphabet| PoolKey::Image(alphabet.clone(), captcha_len))
+                .collect(),
+            "animated" => alphabets()
+                .map(|alphabet| PoolKey::Animated(alphabet.clone(), captcha_len))
+                .collect(),
+            "math" => vec![PoolKey::Math(math_option_count(
+                CaptchaDifficulty::at_level(
+                    0,
+                    captcha_len,
+                    captcha_option_count,
+                    captcha_timeout_secs,
+                ),
+            ))],
+            "grid" => vec![PoolKey::Grid],
+            _ => Vec::new(),
+        };
+        let captcha_pool = (captcha_pool_size > 0 && !pool_keys.is_empty()).then(|| {
+            Arc::new(CaptchaPool::new(
+                captcha_pool_size,
+                captcha_fonts.clone(),
+                captcha_width,
+                captcha_height,
+                pool_keys,
+            ))
+        });
         let captcha_attempts = parse_env_usize("CAPTCHA_ATTEMPTS", 3, 1, 10, &mut warnings);
+        let captcha_min_solve_ms =
+            parse_env_u64("CAPTCHA_MIN_SOLVE_MS", 0, 0, 60_000, &mut warnings);
+        let captcha_refresh_limit =
+            parse_env_usize("CAPTCHA_REFRESH_LIMIT", 2, 0, 10, &mut warnings);
+        let captcha_adaptive = parse_env_bool("CAPTCHA_ADAPTIVE_ENABLED", false, &mut warnings)
+            .then(|| {
+                let window_secs = parse_env_u64(
+                    "CAPTCHA_ADAPTIVE_WINDOW_SECONDS",
+                    600,
+                    60,
+                    86_400,
+                    &mut warnings,
+                );
+                let join_threshold = parse_env_usize(
+                    "CAPTCHA_ADAPTIVE_JOIN_THRESHOLD",
+                    10,
+                    2,
+                    1000,
+                    &mut warnings,
+                );
+                Arc::new(AdaptiveDifficulty::new(window_secs, join_threshold))
+            });
         let captcha_option_digits_to_emoji =
             parse_env_bool("CAPTCHA_OPTION_DIGITS_TO_EMOJI", true, &mut warnings);
+        let captcha_option_obfuscate =
+            parse_env_bool("CAPTCHA_OPTION_OBFUSCATE", false, &mut warnings);
+        let captcha_join_requests =
+            parse_env_bool("CAPTCHA_JOIN_REQUESTS_ENABLED", false, &mut warnings);
+        let captcha_join_approvals =
+            captcha_join_requests.then(|| Arc::new(JoinApprovals::default()));
+        let mut captcha_private_verify =
+            parse_env_bool("CAPTCHA_PRIVATE_VERIFY", false, &mut warnings);
+        if captcha_private_verify && captcha_mode == "webapp" {
+            captcha_private_verify = false;
+            warnings.push(
+                "CAPTCHA_PRIVATE_VERIFY ignored: webapp mode already verifies in the private chat"
+                    .to_string(),
+            );
+        }
+        let captcha_skip_admin_added =
+            parse_env_bool("CAPTCHA_SKIP_ADMIN_ADDED", false, &mut warnings);
+        let captcha_trusted_inviters = parse_trusted_inviters(&mut warnings);
+        let captcha_invite_policies = env::var("CAPTCHA_INVITE_LINK_POLICIES")
+            .map(|raw| InvitePolicies::parse(&raw, &mut warnings))
+            .unwrap_or_default();
         let delete_join_message = parse_env_bool("DELETE_JOIN_MESSAGE", true, &mut warnings);
         let delete_left_message = parse_env_bool("DELETE_LEFT_MESSAGE", true, &mut warnings);
         let ban_release_enabled = parse_env_bool("BAN_RELEASE_ENABLED", false, &mut warnings);
@@ -89,6 +352,39 @@ impl Config {
         );
         let ban_release_db_path =
             env::var("BAN_RELEASE_DB_PATH").unwrap_or_else(|_| "/data/buktikan.sqlite".to_string());
+        let captcha_trust =
+            parse_env_bool("CAPTCHA_TRUST_ENABLED", false, &mut warnings).then(|| {
+                let ttl_secs = parse_env_u64(
+                    "CAPTCHA_TRUST_TTL_SECONDS",
+                    2_592_000,
+                    3600,
+                    31_536_000,
+                    &mut warnings,
+                );
+                let action = env::var("CAPTCHA_TRUST_ACTION")
+                    .ok()
+                    .and_then(|raw| match raw.trim().to_ascii_lowercase().as_str() {
+                        "skip" => Some(TrustAction::Skip),
+                        "light" => Some(TrustAction::Light),
+                        _ => {
+                            warnings.push(format!(
+                                "CAPTCHA_TRUST_ACTION invalid ('{}'), using skip",
+                                sanitize_log_text(&raw)
+                            ));
+                            None
+                        }
+                    })
+                    .unwrap_or(TrustAction::Skip);
+                let scopes = parse_trust_scopes(&mut warnings);
+                Arc::new(CaptchaTrust::new(
+                    ban_release_db_path.clone(),
+                    ttl_secs,
+                    action,
+                    scopes,
+                ))
+            });
+        let captcha_persist_enabled =
+            parse_env_bool("CAPTCHA_PERSIST_ENABLED", true, &mut warnings);
         let log_enabled = parse_env_bool("LOG_ENABLED", true, &mut warnings);
         let log_json = parse_env_bool("LOG_JSON", false, &mut warnings);
         let log_level = env::var("LOG_LEVEL")
@@ -105,27 +401,6 @@ impl Config {
             .unwrap_or(LogLevel::Info);
         let mut captcha_log_enabled = parse_env_bool("CAPTCHA_LOG_ENABLED", false, &mut warnings);
         let captcha_log_chat_id = parse_env_i64("CAPTCHA_LOG_CHAT_ID", &mut warnings);
-        let run_mode = match env::var("RUN_MODE").ok() {
-            Some(raw) => parse_run_mode(&raw).ok_or_else(|| {
-                format!(
-                    "RUN_MODE invalid ('{}'), expected polling|webhook",
-                    sanitize_log_text(&raw)
-                )
-            })?,
-            None => RunMode::Polling,
-        };
-        let webhook_path = normalize_webhook_path(
-            env::var("WEBHOOK_PATH").unwrap_or_else(|_| "/telegram".to_string()),
-        );
-        let webhook_url = env::var("WEBHOOK_URL")
-            .ok()
-            .map(|raw| parse_webhook_url(&raw, &webhook_path))
-            .transpose()?;
-        let webhook_listen_addr = parse_webhook_listen_addr(&mut warnings);
-        let webhook_secret_token = env::var("WEBHOOK_SECRET_TOKEN")
-            .ok()
-            .map(|raw| validate_webhook_secret(&raw))
-            .transpose()?;
         let timezone = env::var("TIMEZONE")
             .ok()
             .and_then(|v| Tz::from_str(v.trim()).ok())
@@ -145,19 +420,41 @@ impl Config {
 
         Ok(Self {
             token,
+            captcha_mode,
+            captcha_question_bank,
             captcha_len,
+            captcha_alphabet,
+            captcha_chat_alphabets,
+            captcha_case_sensitive,
             captcha_timeout_secs,
             captcha_caption_update_secs,
             captcha_width,
             captcha_height,
+            captcha_fonts,
+            captcha_pool,
+            captcha_voice,
             captcha_option_count,
             captcha_attempts,
+            captcha_refresh_limit,
+            captcha_min_solve_ms,
+            captcha_adaptive,
+            captcha_webapp,
             captcha_option_digits_to_emoji,
+            captcha_option_obfuscate,
+            captcha_join_requests,
+            captcha_join_approvals,
+            captcha_private_verify,
+            bot_username: OnceLock::new(),
+            captcha_skip_admin_added,
+            captcha_trusted_inviters,
+            captcha_trust,
+            captcha_invite_policies,
             delete_join_message,
             delete_left_message,
             ban_release_enabled,
             ban_release_after_secs,
             ban_release_db_path,
+            captcha_persist_enabled,
             log_enabled,
             log_json,
             log_level,
@@ -171,6 +468,28 @@ impl Config {
             webhook_secret_token,
         })
     }
+
+    pub fn captcha_alphabet(&self, chat_id: ChatId) -> &CaptchaAlphabet {
+        self.captcha_chat_alphabets
+            .get(&chat_id)
+            .unwrap_or(&self.captcha_alphabet)
+    }
+
+    /// Code length, option count and timeout for a new captcha in `chat_id`, raised from the
+    /// configured baseline when adaptive difficulty is on and the chat is under a join wave.
+    pub fn captcha_difficulty(&self, chat_id: ChatId) -> CaptchaDifficulty {
+        let level = self
+            .captcha_adaptive
+            .as_ref()
+            .map(|adaptive| adaptive.level(chat_id, Utc::now().timestamp()))
+            .unwrap_or(0);
+        CaptchaDifficulty::at_level(
+            level,
+            self.captcha_len,
+            self.captcha_option_count,
+            self.captcha_timeout_secs,
+        )
+    }
 }
 
 pub fn parse_log_level(input: &str) -> Option<LogLevel> {
@@ -190,6 +509,106 @@ fn parse_run_mode(input: &str) -> Option<RunMode> {
     }
 }
 
+/// `CAPTCHA_CHAT_ALPHABETS`: comma-separated `<chat id>=<alphabet>` pairs.
+fn parse_chat_alphabets(
+    case_sensitive: bool,
+    warnings: &mut Vec<String>,
+) -> HashMap<ChatId, CaptchaAlphabet> {
+    let mut alphabets = HashMap::new();
+    let Ok(raw) = env::var("CAPTCHA_CHAT_ALPHABETS") else {
+        return alphabets;
+    };
+    for entry in raw
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let parsed = entry
+            .split_once('=')
+            .ok_or_else(|| "expected <chat id>=<alphabet>".to_string())
+            .and_then(|(chat_id, spec)| {
+                let chat_id = chat_id
+                    .trim()
+                    .parse::<i64>()
+                    .map_err(|_| "chat id is not a number".to_string())?;
+                let alphabet = CaptchaAlphabet::parse(spec, case_sensitive, warnings)?;
+                Ok((ChatId(chat_id), alphabet))
+            });
+        match parsed {
+            Ok((chat_id, alphabet)) => {
+                alphabets.insert(chat_id, alphabet);
+            }
+            Err(err) => warnings.push(format!(
+                "CAPTCHA_CHAT_ALPHABETS entry '{}' ignored: {err}",
+                sanitize_log_text(entry)
+            )),
+        }
+    }
+    alphabets
+}
+
+/// `CAPTCHA_TRUSTED_INVITERS`: comma-separated user ids whose added members skip the captcha.
+fn parse_trusted_inviters(warnings: &mut Vec<String>) -> HashSet<UserId> {
+    let Ok(raw) = env::var("CAPTCHA_TRUSTED_INVITERS") else {
+        return HashSet::new();
+    };
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| match entry.parse::<u64>() {
+            Ok(id) => Some(UserId(id)),
+            Err(_) => {
+                warnings.push(format!(
+                    "CAPTCHA_TRUSTED_INVITERS entry '{}' ignored: user id is not a number",
+                    sanitize_log_text(entry)
+                ));
+                None
+            }
+        })
+        .collect()
+}
+
+/// `CAPTCHA_TRUST_SCOPES`: `;`-separated chat groups sharing trust, each
+/// `<name>=<chat id>,<chat id>,...`.
+fn parse_trust_scopes(warnings: &mut Vec<String>) -> HashMap<ChatId, String> {
+    let mut scopes = HashMap::new();
+    let Ok(raw) = env::var("CAPTCHA_TRUST_SCOPES") else {
+        return scopes;
+    };
+    for entry in raw
+        .split(';')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let Some((name, chats)) = entry
+            .split_once('=')
+            .filter(|(name, _)| !name.trim().is_empty())
+        else {
+            warnings.push(format!(
+                "CAPTCHA_TRUST_SCOPES entry '{}' ignored: expected <name>=<chat id>,...",
+                sanitize_log_text(entry)
+            ));
+            continue;
+        };
+        for chat in chats
+            .split(',')
+            .map(str::trim)
+            .filter(|chat| !chat.is_empty())
+        {
+            match chat.parse::<i64>() {
+                Ok(chat_id) => {
+                    scopes.insert(ChatId(chat_id), name.trim().to_string());
+                }
+                Err(_) => warnings.push(format!(
+                    "CAPTCHA_TRUST_SCOPES chat '{}' ignored: chat id is not a number",
+                    sanitize_log_text(chat)
+                )),
+            }
+        }
+    }
+    scopes
+}
+
 fn parse_env_usize(
     name: &str,
     default: usize,
@@ -458,6 +877,62 @@ mod tests {
         assert_eq!(url.as_str(), "https://example.com/tg");
     }
 
+    #[test]
+    fn captcha_mode_invalid_falls_back_to_image() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut vars = base_required_env();
+        vars.push(("CAPTCHA_MODE", "puzzle"));
+        let _guard = EnvGuard::set(&vars, &["RUN_MODE", "WEBHOOK_URL"]);
+        let cfg = Config::from_env().unwrap();
+        assert_eq!(cfg.captcha_mode, "image");
+        assert!(
+            cfg.config_warnings
+                .iter()
+                .any(|w| w.contains("CAPTCHA_MODE invalid"))
+        );
+    }
+
+    #[test]
+    fn trusted_inviters_skip_invalid_ids() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut vars = base_required_env();
+        vars.push(("CAPTCHA_TRUSTED_INVITERS", "123, abc,,456"));
+        let _guard = EnvGuard::set(&vars, &["RUN_MODE", "WEBHOOK_URL"]);
+        let cfg = Config::from_env().unwrap();
+        assert_eq!(
+            cfg.captcha_trusted_inviters,
+            HashSet::from([UserId(123), UserId(456)])
+        );
+        assert!(
+            cfg.config_warnings
+                .iter()
+                .any(|w| w.contains("CAPTCHA_TRUSTED_INVITERS entry 'abc'"))
+        );
+    }
+
+    #[test]
+    fn trust_scopes_parse_named_groups() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        let mut vars = base_required_env();
+        vars.push(("CAPTCHA_TRUST_ENABLED", "true"));
+        vars.push((
+            "CAPTCHA_TRUST_SCOPES",
+            "jaringan=-1001,-1002; lain=-1003,x;=-1004",
+        ));
+        let _guard = EnvGuard::set(&vars, &["RUN_MODE", "WEBHOOK_URL", "CAPTCHA_TRUST_ACTION"]);
+        let cfg = Config::from_env().unwrap();
+        let trust = cfg.captcha_trust.unwrap();
+        assert_eq!(trust.action(), TrustAction::Skip);
+        assert_eq!(trust.scope_count(), 2);
+        assert_eq!(
+            cfg.config_warnings
+                .iter()
+                .filter(|w| w.contains("CAPTCHA_TRUST_SCOPES"))
+                .count(),
+            2
+        );
+    }
+
     #[test]
     fn webhook_secret_token_validation() {
         let _lock = ENV_LOCK.lock().unwrap();
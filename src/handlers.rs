@@ -5,21 +5,45 @@ use std::time::{Duration, Instant};
 use chrono::Utc;
 use teloxide::prelude::*;
 use teloxide::types::{
-    CallbackQuery, ChatMemberStatus, ChatMemberUpdated, ChatPermissions, InlineKeyboardButton,
-    InlineKeyboardMarkup, InputFile, InputMedia, InputMediaPhoto, Message, ParseMode, UserId,
+    CallbackQuery, ChatInviteLink, ChatJoinRequest, ChatMemberKind, ChatMemberStatus,
+    ChatMemberUpdated, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia,
+    InputMediaAnimation, InputMediaPhoto, Message, MessageId, ParseMode, PollAnswer, PollType,
+    UserId, WebAppInfo,
 };
 
 use crate::ban_release::BanReleaseStore;
 use crate::captcha::{
-    CaptchaCheck, SharedState, captcha_caption, check_captcha_answer, generate_captcha,
-    generate_captcha_options, make_pending_captcha,
+    CaptchaCallback, CaptchaChallenge, CaptchaCheck, CaptchaMessageKind, CaptchaSession,
+    ChallengeProvider, PendingCaptcha, PrivateOrigin, PrivateStart, SessionContext, SharedState,
+    captcha_caption, captcha_poll_question, captcha_session, challenge_provider_for_chat,
+    check_timed_captcha_answer, find_captcha_by_poll, make_pending_captcha, new_captcha_nonce,
+    pending_provider, take_captcha_session,
 };
+use crate::captcha_adaptive::MAX_ADAPTIVE_LEVEL;
+use crate::captcha_audio::{AudioLang, render_captcha_audio};
+use crate::captcha_emoji::EmojiChallenge;
+use crate::captcha_invite::{InvitePolicy, invite_link_label};
+use crate::captcha_private::{PrivateVerifyStub, VERIFY_START_PREFIX};
+use crate::captcha_store::CaptchaStore;
+use crate::captcha_trust::TrustAction;
 use crate::config::{Config, LogLevel};
 use crate::logging::{
-    chat_context, log_message, log_system_level, log_telegram_error, log_user_event_by_display,
-    log_user_event_with_chat,
+    chat_context, log_chat_event, log_message, log_system_level, log_telegram_error,
+    log_user_event_by_display, log_user_event_with_chat,
 };
-use crate::utils::{escape_html, sanitize_log_text};
+use crate::utils::{
+    escape_html, format_solve_time, format_user_display, format_user_name, sanitize_log_text,
+};
+
+/// Everything a captcha needs besides the chat and user it runs for.
+#[derive(Clone)]
+pub struct CaptchaContext {
+    pub bot: Bot,
+    pub state: SharedState,
+    pub config: Arc<Config>,
+    pub ban_release_store: Option<Arc<BanReleaseStore>>,
+    pub captcha_store: Option<Arc<CaptchaStore>>,
+}
 
 pub async fn on_new_members(
     bot: Bot,
@@ -27,6 +51,7 @@ pub async fn on_new_members(
     state: SharedState,
     config: Arc<Config>,
     ban_release_store: Option<Arc<BanReleaseStore>>,
+    captcha_store: Option<Arc<CaptchaStore>>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     let Some(members) = msg.new_chat_members() else {
         return Ok(());
@@ -38,17 +63,52 @@ pub async fn on_new_members(
 
     log_message(&config, &msg);
 
+    // Service messages do not say which invite link was used; with link policies the
+    // chat_member update, which does, starts the captcha instead. Telegram only sends those
+    // to administrators, which the bot already has to be to restrict anyone.
+    if !config.captcha_invite_policies.is_empty() {
+        return Ok(());
+    }
+
+    let ctx = CaptchaContext {
+        bot,
+        state,
+        config,
+        ban_release_store,
+        captcha_store,
+    };
     let (chat_title, chat_username) = chat_context(&msg.chat);
     for member in members {
+        if join_request_approved(
+            &ctx.config,
+            msg.chat.id,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            member,
+        ) {
+            continue;
+        }
+        if skip_captcha_for_added(
+            &ctx.bot,
+            &ctx.config,
+            msg.chat.id,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            msg.from(),
+            member,
+        )
+        .await
+        {
+            continue;
+        }
         start_captcha_for_user(
-            &bot,
+            &ctx,
             msg.chat.id,
+            None,
+            false,
             chat_title.clone(),
             chat_username.clone(),
             member.clone(),
-            &state,
-            &config,
-            &ban_release_store,
         )
         .await?;
     }
@@ -77,6 +137,7 @@ pub async fn on_chat_member_updated(
     state: SharedState,
     config: Arc<Config>,
     ban_release_store: Option<Arc<BanReleaseStore>>,
+    captcha_store: Option<Arc<CaptchaStore>>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     let old_status = update.old_chat_member.status();
     let new_status = update.new_chat_member.status();
@@ -91,36 +152,315 @@ pub async fn on_chat_member_updated(
         return Ok(());
     }
 
+    let ctx = CaptchaContext {
+        bot,
+        state,
+        config,
+        ban_release_store,
+        captcha_store,
+    };
+    let requested = via_join_request(&update);
     let user = update.new_chat_member.user;
     let (chat_title, chat_username) = chat_context(&update.chat);
+    if join_request_approved(
+        &ctx.config,
+        update.chat.id,
+        chat_title.as_deref(),
+        chat_username.as_deref(),
+        &user,
+    ) {
+        return Ok(());
+    }
+    let Some(strict) = apply_invite_policy(
+        &ctx,
+        update.chat.id,
+        chat_title.as_deref(),
+        chat_username.as_deref(),
+        &user,
+        update.invite_link.as_ref(),
+        false,
+    )
+    .await
+    else {
+        return Ok(());
+    };
+    if !strict
+        && !requested
+        && skip_captcha_for_added(
+            &ctx.bot,
+            &ctx.config,
+            update.chat.id,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            Some(&update.from),
+            &user,
+        )
+        .await
+    {
+        return Ok(());
+    }
     start_captcha_for_user(
-        &bot,
+        &ctx,
         update.chat.id,
+        None,
+        strict,
         chat_title,
         chat_username,
         user,
-        &state,
-        &config,
-        &ban_release_store,
     )
     .await?;
     Ok(())
 }
 
-async fn start_captcha_for_user(
+/// Applies the policy of the invite link `user` joined, or asked to join, through. Returns
+/// `None` when that settles it (skipped or banned), otherwise whether the captcha runs in
+/// strict mode.
+async fn apply_invite_policy(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    chat_title: Option<&str>,
+    chat_username: Option<&str>,
+    user: &teloxide::types::User,
+    link: Option<&ChatInviteLink>,
+    join_request: bool,
+) -> Option<bool> {
+    let CaptchaContext { bot, config, .. } = ctx;
+    let policy = config.captcha_invite_policies.for_link(link);
+    let label = link.map(invite_link_label).unwrap_or_default();
+    match policy {
+        InvitePolicy::Default => Some(false),
+        InvitePolicy::Strict => Some(true),
+        InvitePolicy::Skip => {
+            if join_request
+                && let Err(err) = approve_join_request(bot, config, chat_id, user.id).await
+            {
+                log_telegram_error(
+                    config,
+                    LogLevel::Error,
+                    chat_id,
+                    chat_title,
+                    chat_username,
+                    "failed to approve join request",
+                    &err,
+                );
+            }
+            log_user_event_with_chat(
+                config,
+                user,
+                chat_id,
+                chat_title,
+                chat_username,
+                &format!("-> ⏭️ captcha skipped (invite link '{label}')"),
+            );
+            None
+        }
+        InvitePolicy::Ban => {
+            if join_request {
+                let _ = bot.decline_chat_join_request(chat_id, user.id).await;
+            }
+            ban_user_and_maybe_release(
+                ctx,
+                chat_id,
+                chat_title,
+                chat_username,
+                BanTarget {
+                    id: user.id,
+                    name: format_user_name(user),
+                    username: user.username.clone(),
+                },
+                "failed to ban user joining through a banned invite link",
+            )
+            .await;
+            log_user_event_with_chat(
+                config,
+                user,
+                chat_id,
+                chat_title,
+                chat_username,
+                &format!("-> ⛔ banned on join (invite link '{label}')"),
+            );
+            None
+        }
+    }
+}
+
+/// Whether the join in `update` came through a join request, where `from` is whoever
+/// approved it rather than someone vouching for the member. teloxide does not expose the
+/// Bot API's `via_join_request` flag yet, so a link that creates join requests stands in
+/// for it.
+fn via_join_request(update: &ChatMemberUpdated) -> bool {
+    update
+        .invite_link
+        .as_ref()
+        .is_some_and(|link| link.creates_join_request)
+}
+
+/// Whether `member` was added by someone trusted to vouch for them: an administrator who
+/// may invite users, or one of the trusted inviters. Skips are logged. Members who joined
+/// on their own (`adder` is the member) always get the captcha.
+async fn skip_captcha_for_added(
     bot: &Bot,
+    config: &Config,
+    chat_id: ChatId,
+    chat_title: Option<&str>,
+    chat_username: Option<&str>,
+    adder: Option<&teloxide::types::User>,
+    member: &teloxide::types::User,
+) -> bool {
+    if !config.captcha_skip_admin_added {
+        return false;
+    }
+    let Some(adder) = adder.filter(|adder| adder.id != member.id) else {
+        return false;
+    };
+    let reason = if config.captcha_trusted_inviters.contains(&adder.id) {
+        "trusted inviter"
+    } else {
+        match bot.get_chat_member(chat_id, adder.id).await {
+            Ok(adder) => match adder.kind {
+                ChatMemberKind::Owner(_) => "owner",
+                ChatMemberKind::Administrator(admin) if admin.can_invite_users => "admin",
+                _ => return false,
+            },
+            Err(err) => {
+                log_telegram_error(
+                    config,
+                    LogLevel::Warn,
+                    chat_id,
+                    chat_title,
+                    chat_username,
+                    "failed to check who added the member",
+                    &err,
+                );
+                return false;
+            }
+        }
+    };
+    log_user_event_with_chat(
+        config,
+        member,
+        chat_id,
+        chat_title,
+        chat_username,
+        &format!(
+            "-> ⏭️ captcha skipped (added by {reason} {})",
+            format_user_display(adder)
+        ),
+    );
+    true
+}
+
+/// Answers a join request with a captcha in the applicant's private chat. Requests the bot
+/// cannot handle (disabled, or another join request captcha still running for the user)
+/// are left for the group admins.
+pub async fn on_chat_join_request(
+    bot: Bot,
+    request: ChatJoinRequest,
+    state: SharedState,
+    config: Arc<Config>,
+    ban_release_store: Option<Arc<BanReleaseStore>>,
+    captcha_store: Option<Arc<CaptchaStore>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !config.captcha_join_requests || request.from.is_bot {
+        return Ok(());
+    }
+    let ctx = CaptchaContext {
+        bot,
+        state,
+        config,
+        ban_release_store,
+        captcha_store,
+    };
+    let user = request.from;
+    let private_chat = ChatId::from(user.id);
+    let (chat_title, chat_username) = chat_context(&request.chat);
+    let Some(strict) = apply_invite_policy(
+        &ctx,
+        request.chat.id,
+        chat_title.as_deref(),
+        chat_username.as_deref(),
+        &user,
+        request.invite_link.as_ref(),
+        true,
+    )
+    .await
+    else {
+        return Ok(());
+    };
+    let busy = {
+        let guard = ctx.state.lock().await;
+        guard.contains_key(&(private_chat, user.id))
+    };
+    if busy {
+        log_user_event_with_chat(
+            &ctx.config,
+            &user,
+            request.chat.id,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            "<- ⏸️ join request left for admins (another captcha is running)",
+        );
+        return Ok(());
+    }
+
+    let intro = format!(
+        "👋 Permintaan bergabung ke <b>{}</b> diterima. Selesaikan captcha di bawah agar permintaanmu disetujui.",
+        escape_html(chat_title.as_deref().unwrap_or("grup"))
+    );
+    let started = match ctx
+        .bot
+        .send_message(private_chat, intro)
+        .parse_mode(ParseMode::Html)
+        .await
+    {
+        Ok(_) => {
+            start_captcha_for_user(
+                &ctx,
+                private_chat,
+                Some(request.chat.id),
+                strict,
+                chat_title.clone(),
+                chat_username.clone(),
+                user.clone(),
+            )
+            .await
+        }
+        Err(err) => Err(err.into()),
+    };
+    if let Err(err) = started {
+        log_telegram_error(
+            &ctx.config,
+            LogLevel::Error,
+            request.chat.id,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            "failed to send join request captcha",
+            &err,
+        );
+    }
+    Ok(())
+}
+
+/// Sends a captcha for `user` in `chat_id`. With `join_request_chat`, `chat_id` is the
+/// applicant's private chat and the captcha decides the join request to that group. With
+/// private verification, a group only gets a stub linking to the bot's private chat.
+/// `strict` comes from the invite link policy.
+async fn start_captcha_for_user(
+    ctx: &CaptchaContext,
     chat_id: ChatId,
+    join_request_chat: Option<ChatId>,
+    strict: bool,
     chat_title: Option<String>,
     chat_username: Option<String>,
     user: teloxide::types::User,
-    state: &SharedState,
-    config: &Arc<Config>,
-    ban_release_store: &Option<Arc<BanReleaseStore>>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     if user.is_bot {
         return Ok(());
     }
 
+    let CaptchaContext {
+        bot, state, config, ..
+    } = ctx;
     {
         let guard = state.lock().await;
         if guard.contains_key(&(chat_id, user.id)) {
@@ -128,10 +468,70 @@ async fn start_captcha_for_user(
         }
     }
 
-    let no_permissions = ChatPermissions::empty();
-    if let Err(err) = bot
-        .restrict_chat_member(chat_id, user.id, no_permissions)
-        .await
+    let guarded_chat = join_request_chat.unwrap_or(chat_id);
+    let trusted = !strict
+        && verified_elsewhere(
+            config,
+            guarded_chat,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            user.id,
+        )
+        .await;
+    if let Some(trust) = &config.captcha_trust
+        && trusted
+        && trust.action() == TrustAction::Skip
+    {
+        log_user_event_with_chat(
+            config,
+            &user,
+            guarded_chat,
+            chat_title.as_deref(),
+            chat_username.as_deref(),
+            "-> 🤝 captcha skipped (verified in a related chat)",
+        );
+        if let Some(group) = join_request_chat {
+            if let Err(err) = approve_join_request(bot, config, group, user.id).await {
+                log_telegram_error(
+                    config,
+                    LogLevel::Error,
+                    group,
+                    chat_title.as_deref(),
+                    chat_username.as_deref(),
+                    "failed to approve join request",
+                    &err,
+                );
+            } else {
+                let _ = bot
+                    .send_message(
+                        chat_id,
+                        format!(
+                            "✅ Kamu sudah terverifikasi di grup terkait. Permintaan bergabung ke <b>{}</b> sudah disetujui.",
+                            escape_html(chat_title.as_deref().unwrap_or("grup"))
+                        ),
+                    )
+                    .parse_mode(ParseMode::Html)
+                    .await;
+            }
+        }
+        return Ok(());
+    }
+
+    // Trusted users get the one-tap emoji captcha; group joins with private verification
+    // only get the deep link stub here.
+    let provider: &dyn ChallengeProvider = if trusted {
+        &EmojiChallenge
+    } else if join_request_chat.is_none() && PrivateVerifyStub.available(config, chat_id) {
+        &PrivateVerifyStub
+    } else {
+        challenge_provider_for_chat(config, guarded_chat)
+    };
+
+    // Join request applicants are not in the group yet, so there is nothing to restrict.
+    if join_request_chat.is_none()
+        && let Err(err) = bot
+            .restrict_chat_member(chat_id, user.id, provider.restricted_permissions())
+            .await
     {
         log_telegram_error(
             config,
@@ -144,38 +544,34 @@ async fn start_captcha_for_user(
         );
     }
 
-    let (code, png) = generate_captcha(
-        config.captcha_len,
-        config.captcha_width,
-        config.captcha_height,
-    )?;
-
-    let caption = captcha_caption(
-        &user,
-        config.captcha_timeout_secs,
-        config.captcha_attempts,
-        config.captcha_attempts,
-    );
-    let options = generate_captcha_options(&code, config.captcha_option_count);
-    let keyboard = build_captcha_keyboard(&options, config.captcha_option_digits_to_emoji);
-    let sent = bot
-        .send_photo(chat_id, InputFile::memory(png))
-        .caption(caption)
-        .parse_mode(ParseMode::Html)
-        .reply_markup(keyboard)
-        .await?;
-
-    let pending = make_pending_captcha(
-        code,
-        sent.id,
-        options,
-        config.captcha_attempts,
-        config.captcha_timeout_secs,
-        &user,
-        chat_title.clone(),
-        chat_username.clone(),
+    record_adaptive_event(
+        config,
+        guarded_chat,
+        chat_title.as_deref(),
+        chat_username.as_deref(),
+        AdaptiveEvent::Join,
     );
+    let difficulty = config.captcha_difficulty(guarded_chat);
+    let mut pending = send_captcha_challenge(
+        ctx,
+        chat_id,
+        guarded_chat,
+        provider,
+        strict,
+        SessionContext {
+            user: &user,
+            chat_title: chat_title.clone(),
+            chat_username: chat_username.clone(),
+            attempts: captcha_attempts(config, strict),
+            timeout_secs: difficulty.timeout_secs,
+        },
+    )
+    .await?;
+    pending.join_request_chat = join_request_chat;
+    pending.trust_check = trusted;
 
+    persist_pending_captcha(ctx, chat_id, &user, &pending).await;
+    let nonce = pending.nonce.clone();
     {
         let mut guard = state.lock().await;
         guard.insert((chat_id, user.id), pending);
@@ -183,118 +579,350 @@ async fn start_captcha_for_user(
     log_user_event_with_chat(
         config,
         &user,
-        chat_id,
+        guarded_chat,
         chat_title.as_deref(),
         chat_username.as_deref(),
-        "-> ⏳ captcha sent",
+        if join_request_chat.is_some() {
+            "-> ⏳ captcha sent for join request"
+        } else if trusted {
+            "-> ⏳ light captcha sent (verified in a related chat)"
+        } else if provider.private_start() == Some(PrivateStart::MoveChallenge) {
+            "-> ⏳ private verification link sent"
+        } else {
+            "-> ⏳ captcha sent"
+        },
     );
 
-    let bot_clone = bot.clone();
-    let state_clone = state.clone();
-    let config_clone = config.clone();
-    let ban_release_store_clone = ban_release_store.clone();
-    let user_clone = user.clone();
+    spawn_captcha_countdown(ctx.clone(), chat_id, user, nonce, difficulty.timeout_secs);
+
+    Ok(())
+}
+
+/// Generates a challenge for `guarded_chat` and sends it to `chat_id`. The returned
+/// session is not stored yet.
+async fn send_captcha_challenge(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    guarded_chat: ChatId,
+    provider: &dyn ChallengeProvider,
+    strict: bool,
+    session: SessionContext<'_>,
+) -> Result<PendingCaptcha, Box<dyn Error + Send + Sync>> {
+    let CaptchaContext { bot, config, .. } = ctx;
+    let user = session.user;
+    let mut challenge = provider.generate(config, guarded_chat)?;
+    let media = challenge.media.take();
+    let refreshes = if provider.offers_refresh() && !strict {
+        config.captcha_refresh_limit
+    } else {
+        0
+    };
+    // The message id is only known once the message is sent.
+    let mut pending = make_pending_captcha(provider.name(), challenge, MessageId(0), session);
+    pending.nonce = new_captcha_nonce();
+    pending.refreshes_left = refreshes;
+    pending.refreshes_total = refreshes;
+    pending.case_sensitive = provider.case_sensitive(config);
+    pending.strict = strict;
+
+    let caption = captcha_caption(user, &pending);
+    let keyboard = provider.keyboard(&pending.options, config, &pending.nonce, refreshes > 0);
+    let sent = match (provider.message_kind(), media) {
+        (CaptchaMessageKind::Poll, _) => {
+            send_captcha_poll(
+                bot,
+                chat_id,
+                user,
+                pending.prompt.as_deref(),
+                &pending.options,
+                &pending.code,
+                pending.remaining_secs,
+            )
+            .await?
+        }
+        (CaptchaMessageKind::Animation, Some(gif)) => {
+            bot.send_animation(chat_id, InputFile::memory(gif).file_name("captcha.gif"))
+                .caption(caption)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?
+        }
+        (_, Some(png)) => {
+            bot.send_photo(chat_id, InputFile::memory(png))
+                .caption(caption)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?
+        }
+        (_, None) => {
+            bot.send_message(chat_id, caption)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await?
+        }
+    };
+
+    pending.captcha_message_id = sent.id;
+    pending.poll_id = sent.poll().map(|poll| poll.id.clone());
+    Ok(pending)
+}
+
+/// Attempts a new captcha gets; strict invite links allow only one.
+fn captcha_attempts(config: &Config, strict: bool) -> usize {
+    if strict { 1 } else { config.captcha_attempts }
+}
+
+/// Reloads pending captchas saved before a restart. Sessions that are still valid get their
+/// countdown resumed; sessions that expired while the bot was down are handled as timeouts.
+pub async fn resume_pending_captchas(ctx: &CaptchaContext) {
+    let config = &ctx.config;
+    let Some(store) = &ctx.captcha_store else {
+        return;
+    };
+    let stored = match store.fetch_pending().await {
+        Ok(stored) => stored,
+        Err(err) => {
+            log_system_level(
+                config,
+                LogLevel::Warn,
+                &format!("failed to load pending captchas: {err}"),
+            );
+            return;
+        }
+    };
+
+    let now = Utc::now().timestamp();
+    let mut resumed = 0usize;
+    let mut expired = 0usize;
+    for record in stored {
+        let chat_id = record.chat_id;
+        let user = record.user;
+        let mut pending = record.pending;
+        // Sessions saved before buttons carried a nonce get one; the next caption update
+        // puts it on the keyboard.
+        if pending.nonce.is_empty() {
+            pending.nonce = new_captcha_nonce();
+        }
+        let remaining = pending.expires_at.saturating_sub(now);
+        if remaining <= 0 {
+            expired += 1;
+            finish_captcha_timeout(
+                ctx,
+                chat_id,
+                &user,
+                pending,
+                "-> 🏌🏻‍♂️captcha expired while offline",
+            )
+            .await;
+            continue;
+        }
+
+        resumed += 1;
+        let nonce = pending.nonce.clone();
+        {
+            let mut guard = ctx.state.lock().await;
+            guard.insert((chat_id, user.id), pending);
+        }
+        spawn_captcha_countdown(ctx.clone(), chat_id, user, nonce, remaining as u64);
+    }
+
+    if resumed > 0 || expired > 0 {
+        log_system_level(
+            config,
+            LogLevel::Info,
+            &format!("pending captchas restored: resumed={resumed} expired={expired}"),
+        );
+    }
+}
+
+fn spawn_captcha_countdown(
+    ctx: CaptchaContext,
+    chat_id: ChatId,
+    user: teloxide::types::User,
+    nonce: String,
+    timeout: u64,
+) {
     let user_id = user.id;
-    let timeout = config.captcha_timeout_secs;
-    let update_secs = config.captcha_caption_update_secs.max(1);
-    let captcha_message_id = sent.id;
+    let update_secs = ctx.config.captcha_caption_update_secs.max(1);
 
     tokio::spawn(async move {
         let mut remaining = timeout;
         while remaining > 0 {
-            tokio::time::sleep(Duration::from_secs(update_secs)).await;
-            remaining = remaining.saturating_sub(update_secs);
+            let step = update_secs.min(remaining);
+            tokio::time::sleep(Duration::from_secs(step)).await;
+            remaining = remaining.saturating_sub(step);
 
-            let still_pending = {
-                let guard = state_clone.lock().await;
-                guard.contains_key(&(chat_id, user_id))
+            let snapshot = {
+                let mut guard = ctx.state.lock().await;
+                guard
+                    .get_mut(&(chat_id, user_id))
+                    .filter(|pending| pending.nonce == nonce)
+                    .map(|pending| {
+                        pending.remaining_secs = remaining;
+                        pending.clone()
+                    })
             };
-            if !still_pending {
+            let Some(pending) = snapshot else {
                 return;
-            }
-
-            let options_state = {
-                let mut guard = state_clone.lock().await;
-                guard.get_mut(&(chat_id, user_id)).map(|pending| {
-                    pending.remaining_secs = remaining;
-                    (
-                        pending.options.clone(),
-                        pending.attempts_left,
-                        pending.attempts_total,
-                    )
-                })
             };
-            if let Some((options, attempts_left, attempts_total)) = options_state {
-                let caption =
-                    captcha_caption(&user_clone, remaining, attempts_left, attempts_total);
-                let _ = bot_clone
-                    .edit_message_caption(chat_id, captcha_message_id)
-                    .caption(caption)
-                    .parse_mode(ParseMode::Html)
-                    .reply_markup(build_captcha_keyboard(
-                        &options,
-                        config_clone.captcha_option_digits_to_emoji,
-                    ))
-                    .await;
-            }
+            edit_captcha_message(&ctx.bot, &ctx.config, chat_id, &user, &pending, None).await;
         }
 
         let pending = {
-            let mut guard = state_clone.lock().await;
-            guard.remove(&(chat_id, user_id))
+            let mut guard = ctx.state.lock().await;
+            take_captcha_session(&mut guard, (chat_id, user_id), &nonce)
         };
 
         if let Some(pending) = pending {
-            ban_user_and_maybe_release(
-                &bot_clone,
-                &config_clone,
-                chat_id,
-                user_id,
-                pending.chat_title.as_deref(),
-                pending.chat_username.as_deref(),
-                pending.user_name.clone(),
-                pending.user_username.clone(),
-                ban_release_store_clone.clone(),
-                "failed to ban user on timeout",
-            )
-            .await;
-            if let Err(err) = bot_clone
-                .delete_message(chat_id, pending.captcha_message_id)
-                .await
-            {
-                log_telegram_error(
-                    &config_clone,
-                    LogLevel::Error,
-                    chat_id,
-                    pending.chat_title.as_deref(),
-                    pending.chat_username.as_deref(),
-                    "failed to delete captcha message on timeout",
-                    &err,
-                );
-            }
-            log_user_event_by_display(
-                &config_clone,
-                user_id,
-                chat_id,
-                pending.chat_title.as_deref(),
-                pending.chat_username.as_deref(),
-                &pending.user_display,
-                "-> 🏌🏻‍♂️captcha timeout, user banned",
-            );
-            send_captcha_log_if_enabled(
-                &bot_clone,
-                &config_clone,
-                &user_clone,
-                chat_id,
-                pending.chat_title.as_deref(),
-                pending.chat_username.as_deref(),
-                false,
-            )
-            .await;
+            finish_captcha_timeout(&ctx, chat_id, &user, pending, "-> 🏌🏻‍♂️captcha timeout").await;
         }
     });
+}
 
-    Ok(())
+async fn finish_captcha_timeout(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    pending: PendingCaptcha,
+    log_text: &str,
+) {
+    let CaptchaContext { bot, config, .. } = ctx;
+    forget_pending_captcha(ctx, chat_id, user.id).await;
+    let guarded_chat = pending.guarded_chat(chat_id);
+    record_adaptive_event(
+        config,
+        guarded_chat,
+        pending.chat_title.as_deref(),
+        pending.chat_username.as_deref(),
+        AdaptiveEvent::Failure,
+    );
+    let rejected = reject_captcha_user(ctx, chat_id, user.id, &pending, "on timeout").await;
+    delete_captcha_audio(bot, chat_id, &pending).await;
+    if let Err(err) = bot
+        .delete_message(chat_id, pending.captcha_message_id)
+        .await
+    {
+        log_telegram_error(
+            config,
+            LogLevel::Error,
+            chat_id,
+            pending.chat_title.as_deref(),
+            pending.chat_username.as_deref(),
+            "failed to delete captcha message on timeout",
+            &err,
+        );
+    }
+    log_user_event_by_display(
+        config,
+        user.id,
+        guarded_chat,
+        pending.chat_title.as_deref(),
+        pending.chat_username.as_deref(),
+        &pending.user_display,
+        &format!("{log_text}, {rejected}"),
+    );
+    send_captcha_log_if_enabled(ctx, user, guarded_chat, &pending, false, None).await;
+}
+
+/// Refreshes the captcha message with the current countdown and options. A new image replaces
+/// the photo when given; text-only challenges are edited in place.
+async fn edit_captcha_message(
+    bot: &Bot,
+    config: &Config,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    pending: &PendingCaptcha,
+    media: Option<Vec<u8>>,
+) {
+    let provider = pending_provider(pending);
+    let caption = captcha_caption(user, pending);
+    let keyboard = provider.keyboard(
+        &pending.options,
+        config,
+        &pending.nonce,
+        pending.refreshes_left > 0,
+    );
+    let message_id = pending.captcha_message_id;
+    match (provider.message_kind(), media) {
+        // Polls cannot be edited; Telegram shows its own countdown via open_period.
+        (CaptchaMessageKind::Poll, _) => {}
+        (CaptchaMessageKind::Text, _) => {
+            let _ = bot
+                .edit_message_text(chat_id, message_id, caption)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await;
+        }
+        (CaptchaMessageKind::Photo, Some(png)) => {
+            let media = InputMedia::Photo(
+                InputMediaPhoto::new(InputFile::memory(png))
+                    .caption(caption)
+                    .parse_mode(ParseMode::Html),
+            );
+            let _ = bot
+                .edit_message_media(chat_id, message_id, media)
+                .reply_markup(keyboard)
+                .await;
+        }
+        (CaptchaMessageKind::Animation, Some(gif)) => {
+            let media = InputMedia::Animation(
+                InputMediaAnimation::new(InputFile::memory(gif).file_name("captcha.gif"))
+                    .caption(caption)
+                    .parse_mode(ParseMode::Html),
+            );
+            let _ = bot
+                .edit_message_media(chat_id, message_id, media)
+                .reply_markup(keyboard)
+                .await;
+        }
+        (CaptchaMessageKind::Photo | CaptchaMessageKind::Animation, None) => {
+            let _ = bot
+                .edit_message_caption(chat_id, message_id)
+                .caption(caption)
+                .parse_mode(ParseMode::Html)
+                .reply_markup(keyboard)
+                .await;
+        }
+    }
+}
+
+async fn persist_pending_captcha(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    pending: &PendingCaptcha,
+) {
+    let Some(store) = &ctx.captcha_store else {
+        return;
+    };
+    if let Err(err) = store.upsert_pending(chat_id, user, pending).await {
+        log_telegram_error(
+            &ctx.config,
+            LogLevel::Warn,
+            chat_id,
+            pending.chat_title.as_deref(),
+            pending.chat_username.as_deref(),
+            "failed to store pending captcha",
+            &err,
+        );
+    }
+}
+
+async fn forget_pending_captcha(ctx: &CaptchaContext, chat_id: ChatId, user_id: UserId) {
+    let Some(store) = &ctx.captcha_store else {
+        return;
+    };
+    if let Err(err) = store.delete_pending(chat_id, user_id).await {
+        log_telegram_error(
+            &ctx.config,
+            LogLevel::Warn,
+            chat_id,
+            None,
+            None,
+            "failed to delete pending captcha",
+            &err,
+        );
+    }
 }
 
 pub async fn on_text(
@@ -302,7 +930,8 @@ pub async fn on_text(
     msg: Message,
     state: SharedState,
     config: Arc<Config>,
-    _ban_release_store: Option<Arc<BanReleaseStore>>,
+    ban_release_store: Option<Arc<BanReleaseStore>>,
+    captcha_store: Option<Arc<CaptchaStore>>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     let Some(user) = msg.from() else {
         return Ok(());
@@ -311,14 +940,33 @@ pub async fn on_text(
         return Ok(());
     }
 
-    if msg.text().is_some() {
+    // Checked before the captcha text guard: a join request captcha may be running in
+    // this same private chat.
+    let ctx = CaptchaContext {
+        bot: bot.clone(),
+        state: state.clone(),
+        config: config.clone(),
+        ban_release_store,
+        captcha_store,
+    };
+    if msg.chat.is_private()
+        && let Some(nonce) = msg.text().and_then(verify_start_nonce)
+    {
+        return continue_in_private_chat(&ctx, user, msg.chat.id, nonce).await;
+    }
+
+    if let Some(text) = msg.text() {
         let key = (msg.chat.id, user.id);
-        let pending = {
+        let provider = {
             let guard = state.lock().await;
-            guard.contains_key(&key)
+            guard.get(&key).map(pending_provider)
         };
-        if pending {
+        if let Some(provider) = provider {
             let _ = bot.delete_message(msg.chat.id, msg.id).await;
+            if provider.accepts_text() {
+                answer_typed_captcha(&ctx, msg.chat.id, user, text.trim()).await;
+                return Ok(());
+            }
             let (chat_title, chat_username) = chat_context(&msg.chat);
             log_user_event_with_chat(
                 &config,
@@ -393,15 +1041,29 @@ pub async fn on_text(
                 "disabled".to_string()
             };
             let timezone = config.timezone.to_string();
+            let adaptive_info = match &config.captcha_adaptive {
+                Some(adaptive) => {
+                    let now = Utc::now().timestamp();
+                    format!(
+                        "level {}/{} here, {} chat(s) raised",
+                        adaptive.level(msg.chat.id, now),
+                        MAX_ADAPTIVE_LEVEL,
+                        adaptive.raised_levels(now).len()
+                    )
+                }
+                None => "disabled".to_string(),
+            };
             let text = format!(
                 "🧩 *BuktikanBot*\n\
 📦 Version: `{}`\n\
 ⚙️ Mode: `{}`\n\
 🪵 Log: `{}`\n\
+📈 Adaptive: `{}`\n\
 🕒 Timezone: `{}`",
                 escape_markdown_v2(env!("CARGO_PKG_VERSION")),
                 escape_markdown_v2(run_mode),
                 escape_markdown_v2(&log_info),
+                escape_markdown_v2(&adaptive_info),
                 escape_markdown_v2(&timezone)
             );
             if let Err(err) = bot
@@ -434,6 +1096,7 @@ pub async fn on_callback_query(
     state: SharedState,
     config: Arc<Config>,
     ban_release_store: Option<Arc<BanReleaseStore>>,
+    captcha_store: Option<Arc<CaptchaStore>>,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
     let CallbackQuery {
         id,
@@ -445,218 +1108,1103 @@ pub async fn on_callback_query(
     let Some(data) = data.as_deref() else {
         return Ok(());
     };
-    if !data.starts_with("captcha:") {
+    let Some(callback) = CaptchaCallback::parse(data) else {
+        if data.starts_with("captcha") {
+            let _ = bot
+                .answer_callback_query(id)
+                .text("🚫 Tombol ini sudah kedaluwarsa.")
+                .show_alert(true)
+                .await;
+        }
         return Ok(());
-    }
+    };
     let Some(message) = message else {
         return Ok(());
     };
     let chat_id = message.chat.id;
     let key = (chat_id, from.id);
-    let selected = data.trim_start_matches("captcha:");
-
-    let check = {
-        let mut guard = state.lock().await;
-        check_captcha_answer(&mut guard, key, selected)
+    let ctx = CaptchaContext {
+        bot: bot.clone(),
+        state: state.clone(),
+        config: config.clone(),
+        ban_release_store,
+        captcha_store,
     };
 
-    match check {
-        CaptchaCheck::NoPending => {
+    // Buttons only count for the captcha message they were sent with; presses on someone
+    // else's captcha or on an old message are rejected without using up an attempt.
+    let session = {
+        let guard = state.lock().await;
+        captcha_session(&guard, key, callback.nonce(), message.id)
+    };
+    match session {
+        CaptchaSession::NoPending => {
             let _ = bot
                 .answer_callback_query(id)
                 .text("🚫 Captcha sudah selesai atau bukan untukmu.")
                 .show_alert(true)
                 .await;
+            return Ok(());
+        }
+        CaptchaSession::Mismatch => {
+            let _ = bot
+                .answer_callback_query(id)
+                .text("🚫 Tombol ini bukan untuk captcha milikmu atau sudah kedaluwarsa.")
+                .show_alert(true)
+                .await;
+            return Ok(());
+        }
+        CaptchaSession::Current => {}
+    }
+
+    let (answer, source) = match callback {
+        CaptchaCallback::Audio { .. } => {
+            answer_captcha_audio_request(&ctx, chat_id, &from, id).await;
+            return Ok(());
+        }
+        CaptchaCallback::Refresh { .. } => {
+            refresh_captcha(&ctx, chat_id, &from, id).await;
+            return Ok(());
         }
-        CaptchaCheck::Wrong => {
-            let updated = {
+        CaptchaCallback::Answer { index, .. } => {
+            let guard = state.lock().await;
+            let selected = guard
+                .get(&key)
+                .and_then(|pending| pending.options.get(index).cloned())
+                .unwrap_or_default();
+            (selected, "button")
+        }
+        CaptchaCallback::Press { payload, .. } => {
+            let press = {
                 let mut guard = state.lock().await;
                 guard.get_mut(&key).map(|pending| {
-                    pending.attempts_left = pending.attempts_left.saturating_sub(1);
-                    let mut updated_png = None;
-                    let options = if pending.attempts_left == 0 {
-                        generate_captcha_options(&pending.code, config.captcha_option_count)
-                    } else {
-                        match generate_captcha(
-                            config.captcha_len,
-                            config.captcha_width,
-                            config.captcha_height,
-                        ) {
-                            Ok((code, png)) => {
-                                pending.code = code.clone();
-                                updated_png = Some(png);
-                                generate_captcha_options(&code, config.captcha_option_count)
-                            }
-                            Err(err) => {
-                                log_system_level(
-                                    &config,
-                                    LogLevel::Error,
-                                    &format!("failed to regenerate captcha: {err}"),
-                                );
-                                generate_captcha_options(&pending.code, config.captcha_option_count)
-                            }
-                        }
-                    };
-                    pending.options = options.clone();
+                    let provider = pending_provider(pending);
                     (
-                        options,
-                        updated_png,
-                        pending.attempts_left,
-                        pending.attempts_total,
-                        pending.remaining_secs,
+                        provider.on_press(pending, payload),
+                        provider.name(),
+                        pending.clone(),
                     )
                 })
             };
-            if let Some((options, updated_png, attempts_left, attempts_total, remaining_secs)) =
-                updated
-            {
-                if attempts_left == 0 {
-                    let pending = {
-                        let mut guard = state.lock().await;
-                        guard.remove(&key)
+            match press {
+                Some((Some(answer), source, _)) => (answer, source),
+                Some((None, _, pending)) => {
+                    let _ = bot.answer_callback_query(id).await;
+                    edit_captcha_message(&bot, &config, chat_id, &from, &pending, None).await;
+                    return Ok(());
+                }
+                None => (String::new(), "button"),
+            }
+        }
+    };
+
+    let check = {
+        let mut guard = state.lock().await;
+        check_timed_captcha_answer(
+            &mut guard,
+            key,
+            &answer,
+            Utc::now().timestamp_millis(),
+            config.captcha_min_solve_ms,
+        )
+    };
+
+    match check {
+        CaptchaCheck::NoPending => {
+            let _ = bot
+                .answer_callback_query(id)
+                .text("🚫 Captcha sudah selesai atau bukan untukmu.")
+                .show_alert(true)
+                .await;
+        }
+        CaptchaCheck::Wrong | CaptchaCheck::TooFast => {
+            let too_fast = matches!(check, CaptchaCheck::TooFast);
+            let outcome = apply_wrong_answer(
+                &ctx,
+                chat_id,
+                &from,
+                &if too_fast {
+                    format!("{source}, too fast")
+                } else {
+                    source.to_string()
+                },
+            )
+            .await;
+            match outcome {
+                WrongAnswer::Retry => {
+                    let text = if too_fast {
+                        "⚠️ Terlalu cepat! Jawaban dihitung salah, coba lagi."
+                    } else {
+                        "❌ Jawaban salah, coba lagi."
                     };
-                    if let Some(pending) = pending {
-                        ban_user_and_maybe_release(
-                            &bot,
-                            &config,
-                            chat_id,
-                            from.id,
-                            pending.chat_title.as_deref(),
-                            pending.chat_username.as_deref(),
-                            pending.user_name.clone(),
-                            pending.user_username.clone(),
-                            ban_release_store.clone(),
-                            "failed to ban user on attempts exceeded",
-                        )
-                        .await;
-                        if let Err(err) = bot
-                            .delete_message(chat_id, pending.captcha_message_id)
-                            .await
-                        {
-                            log_telegram_error(
-                                &config,
-                                LogLevel::Error,
-                                chat_id,
-                                pending.chat_title.as_deref(),
-                                pending.chat_username.as_deref(),
-                                "failed to delete captcha message on attempts exceeded",
-                                &err,
-                            );
-                        }
-                        log_user_event_by_display(
-                            &config,
-                            from.id,
-                            chat_id,
-                            pending.chat_title.as_deref(),
-                            pending.chat_username.as_deref(),
-                            &pending.user_display,
-                            "-> 🧨 captcha attempts exceeded, user banned",
-                        );
-                        send_captcha_log_if_enabled(
-                            &bot,
-                            &config,
-                            &from,
-                            chat_id,
-                            pending.chat_title.as_deref(),
-                            pending.chat_username.as_deref(),
-                            false,
-                        )
+                    let _ = bot
+                        .answer_callback_query(id)
+                        .text(text)
+                        .show_alert(too_fast)
                         .await;
-                    }
+                }
+                WrongAnswer::Banned => {
                     let _ = bot
                         .answer_callback_query(id)
                         .text("❌ Kesempatan habis. Kamu dikeluarkan.")
                         .show_alert(true)
                         .await;
-                    return Ok(());
                 }
-                let caption = captcha_caption(&from, remaining_secs, attempts_left, attempts_total);
-                if let Some(png) = updated_png {
-                    let media = InputMedia::Photo(
-                        InputMediaPhoto::new(InputFile::memory(png))
-                            .caption(caption)
-                            .parse_mode(ParseMode::Html),
+                WrongAnswer::NoPending => {}
+            }
+        }
+        CaptchaCheck::Verified(pending) => {
+            let _ = bot
+                .answer_callback_query(id)
+                .text("✅ Captcha benar. Terima kasih!")
+                .show_alert(false)
+                .await;
+            complete_verified_captcha(&ctx, chat_id, &from, &pending, source).await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends the spoken code for the "🔊 Audio" button. Never counts as an attempt.
+async fn answer_captcha_audio_request(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    query_id: String,
+) {
+    let CaptchaContext {
+        bot, state, config, ..
+    } = ctx;
+    let key = (chat_id, user.id);
+    let pending = {
+        let guard = state.lock().await;
+        guard
+            .get(&key)
+            .filter(|pending| pending_provider(pending).offers_audio(config))
+            .cloned()
+    };
+    let Some(mut pending) = pending else {
+        let _ = bot
+            .answer_callback_query(query_id)
+            .text("🚫 Captcha sudah selesai atau bukan untukmu.")
+            .show_alert(true)
+            .await;
+        return;
+    };
+    let _ = bot
+        .answer_callback_query(query_id)
+        .text("🔊 Audio dikirim.")
+        .show_alert(false)
+        .await;
+
+    pending.audio_message_id = send_captcha_audio(bot, config, chat_id, user, &pending).await;
+    let still_pending = {
+        let mut guard = state.lock().await;
+        guard
+            .get_mut(&key)
+            .map(|current| current.audio_message_id = pending.audio_message_id)
+            .is_some()
+    };
+    if !still_pending {
+        // Answered or expired while the audio was being sent.
+        delete_captcha_audio(bot, chat_id, &pending).await;
+        return;
+    }
+    persist_pending_captcha(ctx, chat_id, user, &pending).await;
+    log_user_event_with_chat(
+        config,
+        user,
+        chat_id,
+        pending.chat_title.as_deref(),
+        pending.chat_username.as_deref(),
+        "<- 🔊 captcha audio requested",
+    );
+}
+
+/// Sends a voice message speaking the current code as a reply to the captcha, replacing
+/// the previous one. Returns the new message, or `None` when rendering or sending failed.
+async fn send_captcha_audio(
+    bot: &Bot,
+    config: &Config,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    pending: &PendingCaptcha,
+) -> Option<MessageId> {
+    delete_captcha_audio(bot, chat_id, pending).await;
+    let voice = config.captcha_voice.clone()?;
+    let code = pending.code.clone();
+    let lang = AudioLang::for_user(user);
+    let rendered = tokio::task::spawn_blocking(move || render_captcha_audio(&code, &voice, lang))
+        .await
+        .unwrap_or_else(|err| Err(err.into()));
+    let ogg = match rendered {
+        Ok(ogg) => ogg,
+        Err(err) => {
+            log_system_level(
+                config,
+                LogLevel::Error,
+                &format!("failed to render captcha audio: {err}"),
+            );
+            return None;
+        }
+    };
+    match bot
+        .send_voice(chat_id, InputFile::memory(ogg))
+        .reply_to_message_id(pending.captcha_message_id)
+        .await
+    {
+        Ok(sent) => Some(sent.id),
+        Err(err) => {
+            log_telegram_error(
+                config,
+                LogLevel::Warn,
+                chat_id,
+                pending.chat_title.as_deref(),
+                pending.chat_username.as_deref(),
+                "failed to send captcha audio",
+                &err,
+            );
+            None
+        }
+    }
+}
+
+/// The old audio speaks the previous code; after the code rotates, replace it for users
+/// who asked for audio.
+async fn resend_captcha_audio(
+    bot: &Bot,
+    state: &SharedState,
+    config: &Config,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    snapshot: &mut PendingCaptcha,
+) {
+    if snapshot.audio_message_id.is_none() {
+        return;
+    }
+    snapshot.audio_message_id = send_captcha_audio(bot, config, chat_id, user, snapshot).await;
+    let mut guard = state.lock().await;
+    if let Some(current) = guard.get_mut(&(chat_id, user.id)) {
+        current.audio_message_id = snapshot.audio_message_id;
+    }
+}
+
+/// Swaps the challenge for a new one without using up an attempt, for images that are hard
+/// to read. Limited by `refreshes_left`.
+async fn refresh_captcha(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    query_id: String,
+) {
+    let CaptchaContext {
+        bot, state, config, ..
+    } = ctx;
+    let key = (chat_id, user.id);
+    let current = {
+        let guard = state.lock().await;
+        match guard.get(&key) {
+            None => Err("🚫 Captcha sudah selesai atau bukan untukmu."),
+            Some(pending)
+                if pending.refreshes_left == 0 || !pending_provider(pending).offers_refresh() =>
+            {
+                Err("🚫 Batas ganti gambar sudah habis.")
+            }
+            Some(pending) => Ok(pending.clone()),
+        }
+    };
+    let refreshed = match current {
+        Ok(current) => {
+            let nonce = current.nonce.clone();
+            match regenerate_challenge(config, chat_id, current).await {
+                Ok(challenge) => {
+                    let mut guard = state.lock().await;
+                    match guard.get_mut(&key) {
+                        Some(pending) if pending.nonce == nonce && pending.refreshes_left > 0 => {
+                            let media = install_challenge(pending, challenge);
+                            pending.refreshes_left -= 1;
+                            Ok((media, pending.clone()))
+                        }
+                        _ => Err("🚫 Captcha sudah selesai atau bukan untukmu."),
+                    }
+                }
+                Err(err) => {
+                    log_system_level(
+                        config,
+                        LogLevel::Error,
+                        &format!("failed to regenerate captcha: {err}"),
                     );
-                    let _ = bot
-                        .edit_message_media(chat_id, message.id, media)
-                        .reply_markup(build_captcha_keyboard(
-                            &options,
-                            config.captcha_option_digits_to_emoji,
-                        ))
-                        .await;
-                } else {
-                    let _ = bot
-                        .edit_message_caption(chat_id, message.id)
-                        .caption(caption)
-                        .parse_mode(ParseMode::Html)
-                        .reply_markup(build_captcha_keyboard(
-                            &options,
-                            config.captcha_option_digits_to_emoji,
-                        ))
-                        .await;
+                    Err("⚠️ Gagal membuat gambar baru, coba lagi.")
                 }
-                let _ = bot
-                    .answer_callback_query(id)
-                    .text("❌ Jawaban salah, coba lagi.")
-                    .show_alert(false)
-                    .await;
-                let (chat_title, chat_username) = chat_context(&message.chat);
-                log_user_event_with_chat(
-                    &config,
-                    &from,
-                    chat_id,
-                    chat_title.as_deref(),
-                    chat_username.as_deref(),
-                    "<- 🚫 captcha wrong (button)",
+            }
+        }
+        Err(text) => Err(text),
+    };
+    let (media, mut snapshot) = match refreshed {
+        Ok(refreshed) => refreshed,
+        Err(text) => {
+            let _ = bot
+                .answer_callback_query(query_id)
+                .text(text)
+                .show_alert(true)
+                .await;
+            return;
+        }
+    };
+    let _ = bot
+        .answer_callback_query(query_id)
+        .text("🔄 Gambar baru.")
+        .show_alert(false)
+        .await;
+
+    edit_captcha_message(bot, config, chat_id, user, &snapshot, media).await;
+    resend_captcha_audio(bot, state, config, chat_id, user, &mut snapshot).await;
+    persist_pending_captcha(ctx, chat_id, user, &snapshot).await;
+    log_user_event_with_chat(
+        config,
+        user,
+        chat_id,
+        snapshot.chat_title.as_deref(),
+        snapshot.chat_username.as_deref(),
+        "<- 🔄 captcha refreshed",
+    );
+}
+
+async fn delete_captcha_audio(bot: &Bot, chat_id: ChatId, pending: &PendingCaptcha) {
+    if let Some(message_id) = pending.audio_message_id {
+        let _ = bot.delete_message(chat_id, message_id).await;
+    }
+}
+
+pub async fn on_poll_answer(
+    bot: Bot,
+    answer: PollAnswer,
+    state: SharedState,
+    config: Arc<Config>,
+    ban_release_store: Option<Arc<BanReleaseStore>>,
+    captcha_store: Option<Arc<CaptchaStore>>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let PollAnswer {
+        poll_id,
+        user,
+        option_ids,
+    } = answer;
+    let Some(&option_id) = option_ids.first() else {
+        return Ok(());
+    };
+
+    let ctx = CaptchaContext {
+        bot,
+        state,
+        config,
+        ban_release_store,
+        captcha_store,
+    };
+    let check = {
+        let mut guard = ctx.state.lock().await;
+        let Some(key) = find_captcha_by_poll(&guard, &poll_id) else {
+            return Ok(());
+        };
+        // Other members can vote on the quiz too; only the joining user's answer counts.
+        if key.1 != user.id {
+            return Ok(());
+        }
+        let selected = usize::try_from(option_id)
+            .ok()
+            .and_then(|idx| guard.get(&key).and_then(|p| p.options.get(idx).cloned()))
+            .unwrap_or_default();
+        let check = check_timed_captcha_answer(
+            &mut guard,
+            key,
+            &selected,
+            Utc::now().timestamp_millis(),
+            ctx.config.captcha_min_solve_ms,
+        );
+        (key.0, check)
+    };
+
+    match check {
+        (_, CaptchaCheck::NoPending) => {}
+        (chat_id, check @ (CaptchaCheck::Wrong | CaptchaCheck::TooFast)) => {
+            let source = if matches!(check, CaptchaCheck::TooFast) {
+                "poll, too fast"
+            } else {
+                "poll"
+            };
+            apply_wrong_answer(&ctx, chat_id, &user, source).await;
+        }
+        (chat_id, CaptchaCheck::Verified(pending)) => {
+            complete_verified_captcha(&ctx, chat_id, &user, &pending, "poll").await;
+        }
+    }
+
+    Ok(())
+}
+
+async fn answer_typed_captcha(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    answer: &str,
+) {
+    let check = {
+        let mut guard = ctx.state.lock().await;
+        check_timed_captcha_answer(
+            &mut guard,
+            (chat_id, user.id),
+            answer,
+            Utc::now().timestamp_millis(),
+            ctx.config.captcha_min_solve_ms,
+        )
+    };
+    match check {
+        CaptchaCheck::NoPending => {}
+        CaptchaCheck::Wrong | CaptchaCheck::TooFast => {
+            let source = if matches!(check, CaptchaCheck::TooFast) {
+                "text, too fast"
+            } else {
+                "text"
+            };
+            apply_wrong_answer(ctx, chat_id, user, source).await;
+        }
+        CaptchaCheck::Verified(pending) => {
+            complete_verified_captcha(ctx, chat_id, user, &pending, "text").await;
+        }
+    }
+}
+
+/// Result of an answer posted by the Mini App page.
+pub enum WebAppAnswer {
+    NoPending,
+    Verified,
+    Retry,
+    Banned,
+}
+
+/// Checks a proof-of-work counter posted by the Mini App, whose `initData` has already
+/// been validated.
+pub async fn answer_webapp_captcha(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    answer: &str,
+) -> WebAppAnswer {
+    let check = {
+        let mut guard = ctx.state.lock().await;
+        check_timed_captcha_answer(
+            &mut guard,
+            (chat_id, user.id),
+            answer,
+            Utc::now().timestamp_millis(),
+            ctx.config.captcha_min_solve_ms,
+        )
+    };
+    match check {
+        CaptchaCheck::NoPending => WebAppAnswer::NoPending,
+        CaptchaCheck::Wrong | CaptchaCheck::TooFast => {
+            let source = if matches!(check, CaptchaCheck::TooFast) {
+                "web app, too fast"
+            } else {
+                "web app"
+            };
+            match apply_wrong_answer(ctx, chat_id, user, source).await {
+                WrongAnswer::NoPending => WebAppAnswer::NoPending,
+                WrongAnswer::Retry => WebAppAnswer::Retry,
+                WrongAnswer::Banned => WebAppAnswer::Banned,
+            }
+        }
+        CaptchaCheck::Verified(pending) => {
+            complete_verified_captcha(ctx, chat_id, user, &pending, "web app").await;
+            WebAppAnswer::Verified
+        }
+    }
+}
+
+/// Session nonce of a `/start verify_<nonce>` deep link.
+fn verify_start_nonce(text: &str) -> Option<&str> {
+    let mut words = text.split_whitespace();
+    if !words
+        .next()
+        .is_some_and(|command| is_command(command, "start"))
+    {
+        return None;
+    }
+    words.next()?.strip_prefix(VERIFY_START_PREFIX)
+}
+
+const VERIFY_SESSION_MISSING: &str = "🚫 Sesi verifikasi tidak ditemukan atau sudah berakhir.";
+
+/// Answers `/start verify_<nonce>` in the bot's private chat: opens the Mini App of a web
+/// app captcha, or moves a private verification captcha here from its group.
+async fn continue_in_private_chat(
+    ctx: &CaptchaContext,
+    user: &teloxide::types::User,
+    private_chat: ChatId,
+    nonce: &str,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let session = {
+        let guard = ctx.state.lock().await;
+        guard
+            .iter()
+            .find(|((_, user_id), pending)| *user_id == user.id && pending.nonce == nonce)
+            .and_then(|((chat_id, _), pending)| {
+                let start = pending_provider(pending).private_start()?;
+                Some((*chat_id, pending.clone(), start))
+            })
+    };
+    let Some((chat_id, pending, start)) = session else {
+        ctx.bot
+            .send_message(private_chat, VERIFY_SESSION_MISSING)
+            .await?;
+        return Ok(());
+    };
+    if start == PrivateStart::WebAppButton {
+        return send_webapp_button(&ctx.bot, &ctx.config, user, private_chat, chat_id, &pending)
+            .await;
+    }
+    move_captcha_to_private(ctx, user, private_chat, chat_id, pending).await
+}
+
+/// Sends the real challenge behind a group's private verification stub to the user's
+/// private chat and moves the session there. The stub stays until the captcha ends.
+async fn move_captcha_to_private(
+    ctx: &CaptchaContext,
+    user: &teloxide::types::User,
+    private_chat: ChatId,
+    group: ChatId,
+    stub: PendingCaptcha,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let CaptchaContext {
+        bot, state, config, ..
+    } = ctx;
+    let busy = {
+        let guard = state.lock().await;
+        guard.contains_key(&(private_chat, user.id))
+    };
+    if busy {
+        bot.send_message(
+            private_chat,
+            "⏳ Selesaikan dulu captcha yang sedang berjalan di chat ini.",
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let remaining = stub
+        .expires_at
+        .saturating_sub(Utc::now().timestamp())
+        .max(1) as u64;
+    let provider = challenge_provider_for_chat(config, group);
+    let mut pending = send_captcha_challenge(
+        ctx,
+        private_chat,
+        group,
+        provider,
+        stub.strict,
+        SessionContext {
+            user,
+            chat_title: stub.chat_title.clone(),
+            chat_username: stub.chat_username.clone(),
+            attempts: captcha_attempts(config, stub.strict),
+            timeout_secs: remaining,
+        },
+    )
+    .await?;
+    pending.private_origin = Some(PrivateOrigin {
+        chat_id: group,
+        stub_message_id: stub.captcha_message_id,
+    });
+
+    // The stub may have timed out, or another captcha started here, while sending.
+    let claimed = {
+        let mut guard = state.lock().await;
+        let open = guard
+            .get(&(group, user.id))
+            .is_some_and(|current| current.nonce == stub.nonce)
+            && !guard.contains_key(&(private_chat, user.id));
+        if open {
+            guard.remove(&(group, user.id));
+            guard.insert((private_chat, user.id), pending.clone());
+        }
+        open
+    };
+    if !claimed {
+        let _ = bot
+            .delete_message(private_chat, pending.captcha_message_id)
+            .await;
+        bot.send_message(private_chat, VERIFY_SESSION_MISSING)
+            .await?;
+        return Ok(());
+    }
+
+    forget_pending_captcha(ctx, group, user.id).await;
+    persist_pending_captcha(ctx, private_chat, user, &pending).await;
+    let mention = format!(
+        "<a href=\"tg://user?id={}\">{}</a>",
+        user.id.0,
+        escape_html(&user.first_name)
+    );
+    let _ = bot
+        .edit_message_text(
+            group,
+            stub.captcha_message_id,
+            format!("🔐 {mention} sedang menjawab captcha di chat pribadi bot."),
+        )
+        .parse_mode(ParseMode::Html)
+        .await;
+    log_user_event_with_chat(
+        config,
+        user,
+        group,
+        pending.chat_title.as_deref(),
+        pending.chat_username.as_deref(),
+        "<- 🔐 captcha moved to private chat",
+    );
+    spawn_captcha_countdown(
+        ctx.clone(),
+        private_chat,
+        user.clone(),
+        pending.nonce.clone(),
+        remaining,
+    );
+    Ok(())
+}
+
+/// Sends the Mini App button for the web app captcha `pending`, kept under `chat_id`, to
+/// the user's private chat.
+async fn send_webapp_button(
+    bot: &Bot,
+    config: &Config,
+    user: &teloxide::types::User,
+    private_chat: ChatId,
+    chat_id: ChatId,
+    pending: &PendingCaptcha,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Some(webapp) = config.captcha_webapp.as_ref() else {
+        bot.send_message(private_chat, VERIFY_SESSION_MISSING)
+            .await?;
+        return Ok(());
+    };
+    let chat_name = pending.chat_title.as_deref().unwrap_or("grup");
+    let keyboard = InlineKeyboardMarkup::new(vec![vec![InlineKeyboardButton::web_app(
+        "🧩 Buka verifikasi",
+        WebAppInfo {
+            url: webapp.page_url(chat_id, pending),
+        },
+    )]]);
+    bot.send_message(
+        private_chat,
+        format!(
+            "🧩 Verifikasi untuk <b>{}</b>\nTekan tombol di bawah untuk membuka halaman verifikasi.",
+            escape_html(chat_name)
+        ),
+    )
+    .parse_mode(ParseMode::Html)
+    .reply_markup(keyboard)
+    .await?;
+    log_user_event_with_chat(
+        config,
+        user,
+        chat_id,
+        pending.chat_title.as_deref(),
+        pending.chat_username.as_deref(),
+        "<- 🧩 web app captcha opened",
+    );
+    Ok(())
+}
+
+/// Renders a replacement for `pending`'s challenge on the blocking pool, so drawing and
+/// encoding never run on a tokio worker or while the session lock is held.
+async fn regenerate_challenge(
+    config: &Arc<Config>,
+    chat_id: ChatId,
+    pending: PendingCaptcha,
+) -> Result<CaptchaChallenge, Box<dyn Error + Send + Sync>> {
+    let config = Arc::clone(config);
+    tokio::task::spawn_blocking(move || {
+        pending_provider(&pending).regenerate(&pending, &config, pending.guarded_chat(chat_id))
+    })
+    .await?
+}
+
+/// Puts a regenerated challenge into the session and returns its media.
+fn install_challenge(pending: &mut PendingCaptcha, challenge: CaptchaChallenge) -> Option<Vec<u8>> {
+    pending.code = challenge.code;
+    pending.options = challenge.options;
+    pending.prompt = challenge.prompt;
+    pending.provider_data = challenge.data;
+    pending.sent_at_ms = Utc::now().timestamp_millis();
+    challenge.media
+}
+
+enum WrongAnswer {
+    NoPending,
+    Retry,
+    Banned,
+}
+
+/// Burns one attempt. With attempts left, a fresh challenge replaces the current one;
+/// otherwise the user is banned and the captcha message removed.
+async fn apply_wrong_answer(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    source: &str,
+) -> WrongAnswer {
+    let CaptchaContext {
+        bot, state, config, ..
+    } = ctx;
+    let key = (chat_id, user.id);
+    let updated = {
+        let mut guard = state.lock().await;
+        guard.get_mut(&key).map(|pending| {
+            pending.attempts_left = pending.attempts_left.saturating_sub(1);
+            (pending.solve_ms(), pending.clone())
+        })
+    };
+    let Some((solve_ms, mut snapshot)) = updated else {
+        return WrongAnswer::NoPending;
+    };
+    let mut updated_media = None;
+    if snapshot.attempts_left > 0 {
+        match regenerate_challenge(config, chat_id, snapshot.clone()).await {
+            Ok(challenge) => {
+                let mut guard = state.lock().await;
+                match guard.get_mut(&key) {
+                    Some(pending) if pending.nonce == snapshot.nonce => {
+                        updated_media = install_challenge(pending, challenge);
+                        snapshot = pending.clone();
+                    }
+                    // Solved, timed out or replaced while the new challenge was rendered.
+                    _ => return WrongAnswer::NoPending,
+                }
+            }
+            Err(err) => {
+                log_system_level(
+                    config,
+                    LogLevel::Error,
+                    &format!("failed to regenerate captcha: {err}"),
                 );
             }
         }
-        CaptchaCheck::Verified(pending) => {
-            let _ = bot
+    }
+
+    if snapshot.attempts_left == 0 {
+        let pending = {
+            let mut guard = state.lock().await;
+            guard.remove(&key)
+        };
+        if let Some(pending) = pending {
+            forget_pending_captcha(ctx, chat_id, user.id).await;
+            let guarded_chat = pending.guarded_chat(chat_id);
+            record_adaptive_event(
+                config,
+                guarded_chat,
+                pending.chat_title.as_deref(),
+                pending.chat_username.as_deref(),
+                AdaptiveEvent::Failure,
+            );
+            let rejected =
+                reject_captcha_user(ctx, chat_id, user.id, &pending, "on attempts exceeded").await;
+            delete_captcha_audio(bot, chat_id, &pending).await;
+            if let Err(err) = bot
                 .delete_message(chat_id, pending.captcha_message_id)
-                .await;
-            if let Err(err) = restore_chat_permissions(&bot, chat_id, from.id).await {
-                let (chat_title, chat_username) = chat_context(&message.chat);
+                .await
+            {
                 log_telegram_error(
-                    &config,
+                    config,
                     LogLevel::Error,
                     chat_id,
-                    chat_title.as_deref(),
-                    chat_username.as_deref(),
-                    "failed to restore user permissions",
+                    pending.chat_title.as_deref(),
+                    pending.chat_username.as_deref(),
+                    "failed to delete captcha message on attempts exceeded",
                     &err,
                 );
             }
-            let _ = bot
-                .answer_callback_query(id)
-                .text("✅ Captcha benar. Terima kasih!")
-                .show_alert(false)
-                .await;
-            let (chat_title, chat_username) = chat_context(&message.chat);
-            log_user_event_with_chat(
-                &config,
-                &from,
-                chat_id,
-                chat_title.as_deref(),
-                chat_username.as_deref(),
-                "==> ✅ captcha verified (button)",
+            log_user_event_by_display(
+                config,
+                user.id,
+                guarded_chat,
+                pending.chat_title.as_deref(),
+                pending.chat_username.as_deref(),
+                &pending.user_display,
+                &format!(
+                    "-> 🧨 captcha attempts exceeded ({}), {rejected}",
+                    describe_answer(source, solve_ms)
+                ),
             );
-            send_captcha_log_if_enabled(
-                &bot,
-                &config,
-                &from,
-                chat_id,
-                chat_title.as_deref(),
-                chat_username.as_deref(),
-                true,
-            )
-            .await;
+            send_captcha_log_if_enabled(ctx, user, guarded_chat, &pending, false, solve_ms).await;
         }
+        return WrongAnswer::Banned;
+    }
+
+    if pending_provider(&snapshot).message_kind() == CaptchaMessageKind::Poll {
+        resend_captcha_poll(ctx, chat_id, user, snapshot.clone()).await;
+    } else {
+        edit_captcha_message(bot, config, chat_id, user, &snapshot, updated_media).await;
+        resend_captcha_audio(bot, state, config, chat_id, user, &mut snapshot).await;
+        persist_pending_captcha(ctx, chat_id, user, &snapshot).await;
+    }
+    log_user_event_with_chat(
+        config,
+        user,
+        chat_id,
+        snapshot.chat_title.as_deref(),
+        snapshot.chat_username.as_deref(),
+        &format!(
+            "<- 🚫 captcha wrong ({})",
+            describe_answer(source, solve_ms)
+        ),
+    );
+    WrongAnswer::Retry
+}
+
+/// What adaptive difficulty counts towards a spam wave.
+#[derive(Clone, Copy)]
+enum AdaptiveEvent {
+    Join,
+    Failure,
+}
+
+/// Feeds a join or failed captcha into adaptive difficulty and logs any level change.
+fn record_adaptive_event(
+    config: &Config,
+    chat_id: ChatId,
+    chat_title: Option<&str>,
+    chat_username: Option<&str>,
+    event: AdaptiveEvent,
+) {
+    let Some(adaptive) = &config.captcha_adaptive else {
+        return;
+    };
+    let now = Utc::now().timestamp();
+    let change = match event {
+        AdaptiveEvent::Join => adaptive.record_join(chat_id, now),
+        AdaptiveEvent::Failure => adaptive.record_failure(chat_id, now),
+    };
+    let Some(change) = change else {
+        return;
+    };
+    let difficulty = config.captcha_difficulty(chat_id);
+    let (icon, level) = if change.to > change.from {
+        ("📈", LogLevel::Warn)
+    } else {
+        ("📉", LogLevel::Info)
+    };
+    log_chat_event(
+        config,
+        level,
+        chat_id,
+        chat_title,
+        chat_username,
+        &format!(
+            "{icon} captcha difficulty level {} -> {} (joins={}, failures={}; len={}, options={}, timeout={}s)",
+            change.from,
+            change.to,
+            change.joins,
+            change.failures,
+            difficulty.len,
+            difficulty.option_count,
+            difficulty.timeout_secs
+        ),
+    );
+}
+
+/// Answer source plus the measured solve time, e.g. `button, 0.31s`.
+fn describe_answer(source: &str, solve_ms: Option<i64>) -> String {
+    match solve_ms {
+        Some(solve_ms) => format!("{source}, {}", format_solve_time(solve_ms)),
+        None => source.to_string(),
     }
+}
 
+/// Approves a join request and remembers it, so the join that follows is not challenged.
+async fn approve_join_request(
+    bot: &Bot,
+    config: &Config,
+    chat_id: ChatId,
+    user_id: UserId,
+) -> Result<(), teloxide::RequestError> {
+    bot.approve_chat_join_request(chat_id, user_id).await?;
+    if let Some(approvals) = &config.captcha_join_approvals {
+        approvals.record(chat_id, user_id, Utc::now().timestamp());
+    }
     Ok(())
 }
 
+/// Whether `user` joining `chat_id` follows a join request this bot just approved; their
+/// captcha is already done, whatever link they used.
+fn join_request_approved(
+    config: &Config,
+    chat_id: ChatId,
+    chat_title: Option<&str>,
+    chat_username: Option<&str>,
+    user: &teloxide::types::User,
+) -> bool {
+    let approved = config
+        .captcha_join_approvals
+        .as_ref()
+        .is_some_and(|approvals| approvals.is_approved(chat_id, user.id, Utc::now().timestamp()));
+    if approved {
+        log_user_event_with_chat(
+            config,
+            user,
+            chat_id,
+            chat_title,
+            chat_username,
+            "-> ✅ captcha skipped (join request already approved)",
+        );
+    }
+    approved
+}
+
+async fn complete_verified_captcha(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    pending: &PendingCaptcha,
+    source: &str,
+) {
+    let CaptchaContext { bot, config, .. } = ctx;
+    forget_pending_captcha(ctx, chat_id, user.id).await;
+    delete_captcha_audio(bot, chat_id, pending).await;
+    let _ = bot
+        .delete_message(chat_id, pending.captcha_message_id)
+        .await;
+    let chat_title = pending.chat_title.as_deref();
+    let chat_username = pending.chat_username.as_deref();
+    let guarded_chat = pending.guarded_chat(chat_id);
+    let admitted = match pending.join_request_chat {
+        Some(group) => approve_join_request(bot, config, group, user.id)
+            .await
+            .map_err(|err| ("failed to approve join request", err.into())),
+        None => restore_chat_permissions(bot, guarded_chat, user.id)
+            .await
+            .map_err(|err| ("failed to restore user permissions", err)),
+    };
+    delete_private_origin_stub(bot, pending).await;
+    if !pending.trust_check {
+        remember_verified_user(config, guarded_chat, chat_title, chat_username, user.id).await;
+    }
+    if let Err((context, err)) = admitted {
+        log_telegram_error(
+            config,
+            LogLevel::Error,
+            guarded_chat,
+            chat_title,
+            chat_username,
+            context,
+            &err,
+        );
+    } else if pending.join_request_chat.is_some() {
+        send_private_notice(
+            bot,
+            chat_id,
+            pending,
+            "✅ Captcha benar. Permintaan bergabung ke <b>{chat}</b> sudah disetujui.",
+        )
+        .await;
+    } else if pending.private_origin.is_some() {
+        send_private_notice(
+            bot,
+            chat_id,
+            pending,
+            "✅ Captcha benar. Kamu sudah bisa mengirim pesan di <b>{chat}</b>.",
+        )
+        .await;
+    }
+    log_user_event_with_chat(
+        config,
+        user,
+        guarded_chat,
+        chat_title,
+        chat_username,
+        &format!(
+            "==> ✅ captcha verified ({})",
+            describe_answer(source, pending.solve_ms())
+        ),
+    );
+    send_captcha_log_if_enabled(ctx, user, guarded_chat, pending, true, pending.solve_ms()).await;
+}
+
+/// Quiz polls cannot be edited or re-answered, so a retry replaces the poll message.
+async fn resend_captcha_poll(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    mut pending: PendingCaptcha,
+) {
+    let CaptchaContext {
+        bot, state, config, ..
+    } = ctx;
+    let _ = bot
+        .delete_message(chat_id, pending.captcha_message_id)
+        .await;
+    let sent = send_captcha_poll(
+        bot,
+        chat_id,
+        user,
+        pending.prompt.as_deref(),
+        &pending.options,
+        &pending.code,
+        pending.remaining_secs,
+    )
+    .await;
+    let sent = match sent {
+        Ok(sent) => sent,
+        Err(err) => {
+            log_telegram_error(
+                config,
+                LogLevel::Error,
+                chat_id,
+                pending.chat_title.as_deref(),
+                pending.chat_username.as_deref(),
+                "failed to resend captcha poll",
+                &err,
+            );
+            return;
+        }
+    };
+    pending.captcha_message_id = sent.id;
+    pending.poll_id = sent.poll().map(|poll| poll.id.clone());
+    {
+        let mut guard = state.lock().await;
+        let Some(current) = guard.get_mut(&(chat_id, user.id)) else {
+            return;
+        };
+        current.captcha_message_id = pending.captcha_message_id;
+        current.poll_id = pending.poll_id.clone();
+    }
+    persist_pending_captcha(ctx, chat_id, user, &pending).await;
+}
+
+async fn send_captcha_poll(
+    bot: &Bot,
+    chat_id: ChatId,
+    user: &teloxide::types::User,
+    prompt: Option<&str>,
+    options: &[String],
+    code: &str,
+    remaining_secs: u64,
+) -> Result<Message, teloxide::RequestError> {
+    let correct = options.iter().position(|opt| opt == code).unwrap_or(0);
+    let mut request = bot
+        .send_poll(
+            chat_id,
+            captcha_poll_question(user, prompt),
+            options.to_vec(),
+        )
+        .type_(PollType::Quiz)
+        .is_anonymous(false)
+        .correct_option_id(correct as u8);
+    // Telegram accepts an open period of 5..=600 seconds.
+    if (5..=600).contains(&remaining_secs) {
+        request = request.open_period(remaining_secs as u16);
+    }
+    request.await
+}
+
 pub async fn on_non_text(
     msg: Message,
     config: Arc<Config>,
@@ -679,19 +2227,183 @@ async fn restore_chat_permissions(
     Ok(())
 }
 
-async fn ban_user_and_maybe_release(
-    bot: &Bot,
-    config: &Arc<Config>,
+/// Keeps a user who failed the captcha out: a member is banned (and maybe released later),
+/// a join request is declined. Returns what happened, for the log line.
+async fn reject_captcha_user(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    user_id: UserId,
+    pending: &PendingCaptcha,
+    when: &str,
+) -> &'static str {
+    let CaptchaContext { bot, config, .. } = ctx;
+    revoke_verified_user(config, pending.guarded_chat(chat_id), pending, user_id).await;
+    let Some(group) = pending.join_request_chat else {
+        ban_user_and_maybe_release(
+            ctx,
+            pending.guarded_chat(chat_id),
+            pending.chat_title.as_deref(),
+            pending.chat_username.as_deref(),
+            BanTarget {
+                id: user_id,
+                name: pending.user_name.clone(),
+                username: pending.user_username.clone(),
+            },
+            &format!("failed to ban user {when}"),
+        )
+        .await;
+        if pending.private_origin.is_some() {
+            delete_private_origin_stub(bot, pending).await;
+            send_private_notice(
+                bot,
+                chat_id,
+                pending,
+                "❌ Captcha gagal. Kamu dikeluarkan dari <b>{chat}</b>.",
+            )
+            .await;
+        }
+        return "user banned";
+    };
+    if let Err(err) = bot.decline_chat_join_request(group, user_id).await {
+        log_telegram_error(
+            config,
+            LogLevel::Error,
+            group,
+            pending.chat_title.as_deref(),
+            pending.chat_username.as_deref(),
+            &format!("failed to decline join request {when}"),
+            &err,
+        );
+    }
+    send_private_notice(
+        bot,
+        chat_id,
+        pending,
+        "❌ Captcha gagal. Permintaan bergabung ke <b>{chat}</b> ditolak.",
+    )
+    .await;
+    "join request declined"
+}
+
+/// Whether the user passed a captcha in a chat sharing trust with `chat_id`. Lookup errors
+/// count as not trusted.
+async fn verified_elsewhere(
+    config: &Config,
     chat_id: ChatId,
+    chat_title: Option<&str>,
+    chat_username: Option<&str>,
     user_id: UserId,
+) -> bool {
+    let Some(trust) = &config.captcha_trust else {
+        return false;
+    };
+    match trust
+        .is_trusted(chat_id, user_id, Utc::now().timestamp())
+        .await
+    {
+        Ok(trusted) => trusted,
+        Err(err) => {
+            log_telegram_error(
+                config,
+                LogLevel::Warn,
+                chat_id,
+                chat_title,
+                chat_username,
+                "failed to look up verified user",
+                &err,
+            );
+            false
+        }
+    }
+}
+
+async fn remember_verified_user(
+    config: &Config,
+    chat_id: ChatId,
     chat_title: Option<&str>,
     chat_username: Option<&str>,
-    user_name: String,
-    user_username: Option<String>,
-    ban_release_store: Option<Arc<BanReleaseStore>>,
+    user_id: UserId,
+) {
+    let Some(trust) = &config.captcha_trust else {
+        return;
+    };
+    if let Err(err) = trust.record(chat_id, user_id, Utc::now().timestamp()).await {
+        log_telegram_error(
+            config,
+            LogLevel::Warn,
+            chat_id,
+            chat_title,
+            chat_username,
+            "failed to store verified user",
+            &err,
+        );
+    }
+}
+
+/// A failed captcha anywhere drops the user's trust in every chat.
+async fn revoke_verified_user(
+    config: &Config,
+    chat_id: ChatId,
+    pending: &PendingCaptcha,
+    user_id: UserId,
+) {
+    let Some(trust) = &config.captcha_trust else {
+        return;
+    };
+    if let Err(err) = trust.revoke(user_id).await {
+        log_telegram_error(
+            config,
+            LogLevel::Warn,
+            chat_id,
+            pending.chat_title.as_deref(),
+            pending.chat_username.as_deref(),
+            "failed to revoke verified user",
+            &err,
+        );
+    }
+}
+
+/// Removes the group stub of a captcha that was moved to the private chat.
+async fn delete_private_origin_stub(bot: &Bot, pending: &PendingCaptcha) {
+    if let Some(origin) = pending.private_origin {
+        let _ = bot
+            .delete_message(origin.chat_id, origin.stub_message_id)
+            .await;
+    }
+}
+
+/// Tells the user, in the private chat their captcha ran in, how it ended for the group.
+/// `template` is HTML with `{chat}` standing for the group title.
+async fn send_private_notice(bot: &Bot, chat_id: ChatId, pending: &PendingCaptcha, template: &str) {
+    let chat = escape_html(pending.chat_title.as_deref().unwrap_or("grup"));
+    let _ = bot
+        .send_message(chat_id, template.replace("{chat}", &chat))
+        .parse_mode(ParseMode::Html)
+        .await;
+}
+
+/// A user to ban, with the names a ban release job logs when lifting the ban.
+struct BanTarget {
+    id: UserId,
+    name: String,
+    username: Option<String>,
+}
+
+async fn ban_user_and_maybe_release(
+    ctx: &CaptchaContext,
+    chat_id: ChatId,
+    chat_title: Option<&str>,
+    chat_username: Option<&str>,
+    target: BanTarget,
     error_context: &str,
 ) {
-    if let Err(err) = bot.ban_chat_member(chat_id, user_id).await {
+    let config = &ctx.config;
+    let BanTarget {
+        id: user_id,
+        name: user_name,
+        username: user_username,
+    } = target;
+    if let Err(err) = ctx.bot.ban_chat_member(chat_id, user_id).await {
         log_telegram_error(
             config,
             LogLevel::Error,
@@ -707,7 +2419,7 @@ async fn ban_user_and_maybe_release(
     if !config.ban_release_enabled {
         return;
     }
-    let Some(store) = ban_release_store else {
+    let Some(store) = &ctx.ban_release_store else {
         return;
     };
     let release_at = Utc::now().timestamp() + config.ban_release_after_secs as i64;
@@ -777,69 +2489,19 @@ fn escape_markdown_v2(input: &str) -> String {
     out
 }
 
-fn option_to_display(input: &str) -> String {
-    let mut out = String::with_capacity(input.len());
-    let mut chars = input.chars().peekable();
-    while let Some(ch) = chars.next() {
-        match ch {
-            'A' | 'a' => {
-                if matches!(chars.peek(), Some('B') | Some('b')) {
-                    chars.next();
-                    out.push_str("🆎");
-                } else {
-                    out.push_str("🅰️");
-                }
-            }
-            'B' | 'b' => out.push_str("🅱️"),
-            '0' => out.push_str("0️⃣"),
-            '1' => out.push_str("1️⃣"),
-            '2' => out.push_str("2️⃣"),
-            '3' => out.push_str("3️⃣"),
-            '4' => out.push_str("4️⃣"),
-            '5' => out.push_str("5️⃣"),
-            '6' => out.push_str("6️⃣"),
-            '7' => out.push_str("7️⃣"),
-            '8' => out.push_str("8️⃣"),
-            '9' => out.push_str("9️⃣"),
-            _ => out.push(ch),
-        }
-    }
-    out
-}
-
-fn build_captcha_keyboard(options: &[String], digits_to_emoji: bool) -> InlineKeyboardMarkup {
-    let rows: Vec<Vec<InlineKeyboardButton>> = options
-        .chunks(3)
-        .map(|chunk| {
-            chunk
-                .iter()
-                .map(|option| {
-                    let display = if digits_to_emoji
-                        && option
-                            .chars()
-                            .any(|ch| ch.is_ascii_digit() || matches!(ch, 'A' | 'a' | 'B' | 'b'))
-                    {
-                        option_to_display(option)
-                    } else {
-                        option.to_string()
-                    };
-                    InlineKeyboardButton::callback(display, format!("captcha:{option}"))
-                })
-                .collect()
-        })
-        .collect();
-    InlineKeyboardMarkup::new(rows)
-}
-
+/// Posts the outcome of `pending` to the captcha log chat. The group shown is the one
+/// saved with the session.
 async fn send_captcha_log_if_enabled(
-    bot: &Bot,
-    config: &Config,
+    ctx: &CaptchaContext,
     user: &teloxide::types::User,
     chat_id: ChatId,
-    chat_title: Option<&str>,
-    chat_username: Option<&str>,
+    pending: &PendingCaptcha,
     success: bool,
+    solve_ms: Option<i64>,
 ) {
+    let CaptchaContext { bot, config, .. } = ctx;
+    let chat_title = pending.chat_title.as_deref();
+    let chat_username = pending.chat_username.as_deref();
     if !config.captcha_log_enabled {
         return;
     }
@@ -874,7 +2536,7 @@ async fn send_captcha_log_if_enabled(
 
     let result = if success { "✅ sukses" } else { "🚫 gagal" };
 
-    let mut lines = Vec::with_capacity(6);
+    let mut lines = Vec::with_capacity(7);
     lines.push("🪵 Captcha Log".to_string());
     lines.push(format!(" ├⏱️ <code>{}</code>", escape_html(&ts)));
     lines.push(format!(" ├🙋🏽 {}", full_name));
@@ -882,6 +2544,12 @@ async fn send_captcha_log_if_enabled(
         lines.push(line);
     }
     lines.push(format!(" ├👥 {}", group_label));
+    if let Some(solve_ms) = solve_ms {
+        lines.push(format!(
+            " ├⚡ Waktu jawab: <code>{}</code>",
+            format_solve_time(solve_ms)
+        ));
+    }
     lines.push(format!(" └{}", result));
     let message = lines.join("\n");
 
@@ -902,3 +2570,40 @@ async fn send_captcha_log_if_enabled(
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn member_joined(from_id: u64, invite_link: serde_json::Value) -> ChatMemberUpdated {
+        let user = |id: u64| serde_json::json!({ "id": id, "is_bot": false, "first_name": "U" });
+        serde_json::from_value(serde_json::json!({
+            "chat": { "id": -100123, "type": "supergroup", "title": "Grup" },
+            "from": user(from_id),
+            "date": 0,
+            "old_chat_member": { "user": user(2), "status": "left" },
+            "new_chat_member": { "user": user(2), "status": "member" },
+            "invite_link": invite_link,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn approved_join_requests_are_not_vouched_for() {
+        let link = |creates_join_request: bool| {
+            serde_json::json!({
+                "invite_link": "https://t.me/+AbCd…",
+                "creator": { "id": 1, "is_bot": false, "first_name": "Admin" },
+                "creates_join_request": creates_join_request,
+                "is_primary": false,
+                "is_revoked": false,
+            })
+        };
+        assert!(via_join_request(&member_joined(1, link(true))));
+        assert!(!via_join_request(&member_joined(1, link(false))));
+        assert!(!via_join_request(&member_joined(
+            1,
+            serde_json::Value::Null
+        )));
+    }
+}
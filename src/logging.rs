@@ -93,6 +93,31 @@ pub fn log_system_level(config: &Config, level: LogLevel, text: &str) {
     );
 }
 
+pub fn log_chat_event(
+    config: &Config,
+    level: LogLevel,
+    chat_id: ChatId,
+    title: Option<&str>,
+    chat_username: Option<&str>,
+    text: &str,
+) {
+    if !log_enabled_at(config, level) {
+        return;
+    }
+    let tz_now = now_in_timezone(config.timezone);
+    let ts = tz_now.format("%Y-%m-%d %H:%M:%S%.6f").to_string();
+    log_line(
+        level,
+        config.log_json,
+        &ts,
+        chat_id,
+        title,
+        chat_username,
+        None,
+        &sanitize_log_text(text),
+    );
+}
+
 pub fn log_user_event_with_chat(
     config: &Config,
     user: &teloxide::types::User,
@@ -174,6 +199,7 @@ pub fn log_telegram_error(
     );
 }
 
+#[allow(clippy::too_many_arguments)]
 fn log_line<T: std::fmt::Display>(
     level: LogLevel,
     log_json: bool,
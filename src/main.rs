@@ -4,11 +4,28 @@ use std::sync::Arc;
 use chrono::TimeZone;
 use teloxide::prelude::*;
 use teloxide::types::{ChatId, ParseMode, UserId};
-use teloxide::update_listeners::webhooks;
+use teloxide::update_listeners::{UpdateListener, webhooks};
 
 mod ban_release;
 mod captcha;
+mod captcha_adaptive;
+mod captcha_alphabet;
+mod captcha_approvals;
+mod captcha_audio;
+mod captcha_emoji;
+mod captcha_grid;
+mod captcha_invite;
+mod captcha_keypad;
+mod captcha_labels;
+mod captcha_math;
+mod captcha_pool;
+mod captcha_private;
+mod captcha_questions;
 mod captcha_quotes;
+mod captcha_render;
+mod captcha_store;
+mod captcha_trust;
+mod captcha_webapp;
 mod config;
 mod handlers;
 mod logging;
@@ -16,9 +33,12 @@ mod utils;
 
 use crate::ban_release::{BanReleaseJob, BanReleaseStore, worker_interval};
 use crate::captcha::SharedState;
+use crate::captcha_pool::{CAPTCHA_POOL_LOG_INTERVAL, CaptchaPool, CaptchaPoolStats};
+use crate::captcha_store::CaptchaStore;
 use crate::config::{Config, LogLevel, RunMode};
 use crate::handlers::{
-    on_callback_query, on_chat_member_updated, on_left_member, on_new_members, on_non_text, on_text,
+    CaptchaContext, on_callback_query, on_chat_join_request, on_chat_member_updated,
+    on_left_member, on_new_members, on_non_text, on_poll_answer, on_text, resume_pending_captchas,
 };
 use crate::logging::{log_system, log_system_block, log_system_level};
 use crate::utils::{escape_html, sanitize_log_text};
@@ -38,20 +58,43 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
 
     let version_line = format!("(system) version: {}", env!("CARGO_PKG_VERSION"));
     let config_line = format!(
-        "(system) config: captcha_len={} timeout={}s update={}s size={}x{} options={} attempts={} option_digits_to_emoji={} delete_join_message={} delete_left_message={} ban_release_enabled={} ban_release_after_secs={} ban_release_db_path={} log_json={} log_level={} captcha_log_enabled={} captcha_log_chat_id={} timezone={} run_mode={}",
+        "(system) config: captcha_mode={} captcha_questions={} captcha_len={} alphabet={} chat_alphabets={} case_sensitive={} timeout={}s update={}s size={}x{} fonts={} pool={} options={} attempts={} adaptive={} webapp={} option_digits_to_emoji={} option_obfuscate={} join_requests={} private_verify={} skip_admin_added={} trusted_inviters={} trust={} invite_policies={} delete_join_message={} delete_left_message={} ban_release_enabled={} ban_release_after_secs={} ban_release_db_path={} captcha_persist_enabled={} log_json={} log_level={} captcha_log_enabled={} captcha_log_chat_id={} timezone={} run_mode={}",
+        config.captcha_mode,
+        config
+            .captcha_question_bank
+            .as_ref()
+            .map_or(0, |bank| bank.question_count()),
         config.captcha_len,
+        config.captcha_alphabet.chars().len(),
+        config.captcha_chat_alphabets.len(),
+        config.captcha_case_sensitive,
         config.captcha_timeout_secs,
         config.captcha_caption_update_secs,
         config.captcha_width,
         config.captcha_height,
+        config.captcha_fonts.len(),
+        config.captcha_pool.as_ref().map_or(0, |pool| pool.depth()),
         config.captcha_option_count,
         config.captcha_attempts,
+        config.captcha_adaptive.is_some(),
+        config.captcha_webapp.is_some(),
         config.captcha_option_digits_to_emoji,
+        config.captcha_option_obfuscate,
+        config.captcha_join_requests,
+        config.captcha_private_verify,
+        config.captcha_skip_admin_added,
+        config.captcha_trusted_inviters.len(),
+        config.captcha_trust.as_ref().map_or_else(
+            || "off".to_string(),
+            |trust| format!("{:?}/{} scopes", trust.action(), trust.scope_count())
+        ),
+        config.captcha_invite_policies.len(),
         config.delete_join_message,
         config.delete_left_message,
         config.ban_release_enabled,
         config.ban_release_after_secs,
         config.ban_release_db_path,
+        config.captcha_persist_enabled,
         config.log_json,
         config.log_level.as_str(),
         config.captcha_log_enabled,
@@ -66,13 +109,19 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
         }
     );
     let bot_username = match bot.get_me().await {
-        Ok(me) => me.username.as_deref().unwrap_or("unknown").to_string(),
+        Ok(me) => me.username.clone(),
         Err(err) => {
             log_system_level(&config, LogLevel::Warn, &format!("getMe failed: {err}"));
-            "unknown".to_string()
+            None
         }
     };
-    let started_line = format!("(system) bot started @{}", bot_username);
+    if let Some(username) = &bot_username {
+        let _ = config.bot_username.set(username.clone());
+    }
+    let started_line = format!(
+        "(system) bot started @{}",
+        bot_username.as_deref().unwrap_or("unknown")
+    );
     log_system_block(
         &config,
         LogLevel::Info,
@@ -99,6 +148,55 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
         None
     };
 
+    let captcha_store = if config.captcha_persist_enabled {
+        match CaptchaStore::init(config.ban_release_db_path.clone()).await {
+            Ok(store) => Some(Arc::new(store)),
+            Err(err) => {
+                log_system_level(
+                    &config,
+                    LogLevel::Error,
+                    &format!("captcha store init failed: {err}"),
+                );
+                None
+            }
+        }
+    } else {
+        None
+    };
+    if let Some(trust) = &config.captcha_trust
+        && let Err(err) = trust.init().await
+    {
+        log_system_level(
+            &config,
+            LogLevel::Error,
+            &format!("captcha trust store init failed: {err}"),
+        );
+    }
+    let captcha_context = CaptchaContext {
+        bot: bot.clone(),
+        state: state.clone(),
+        config: config.clone(),
+        ban_release_store: ban_release_store.clone(),
+        captcha_store: captcha_store.clone(),
+    };
+    resume_pending_captchas(&captcha_context).await;
+
+    if let Some(pool) = config.captcha_pool.clone() {
+        match pool.spawn_worker() {
+            Ok(()) => {
+                let config = config.clone();
+                tokio::spawn(async move {
+                    run_captcha_pool_logger(config, pool).await;
+                });
+            }
+            Err(err) => log_system_level(
+                &config,
+                LogLevel::Error,
+                &format!("captcha pool worker failed to start: {err}"),
+            ),
+        }
+    }
+
     if let Some(store) = ban_release_store.clone() {
         let bot = bot.clone();
         let config = config.clone();
@@ -118,6 +216,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                         let state = state.clone();
                         let config = config.clone();
                         let ban_release_store = ban_release_store.clone();
+                        let captcha_store = captcha_store.clone();
                         move |bot: Bot, msg: teloxide::types::Message| {
                             on_new_members(
                                 bot,
@@ -125,6 +224,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                                 state.clone(),
                                 config.clone(),
                                 ban_release_store.clone(),
+                                captcha_store.clone(),
                             )
                         }
                     }),
@@ -146,6 +246,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                             let state = state.clone();
                             let config = config.clone();
                             let ban_release_store = ban_release_store.clone();
+                            let captcha_store = captcha_store.clone();
                             move |bot: Bot, msg: teloxide::types::Message| {
                                 on_text(
                                     bot,
@@ -153,6 +254,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                                     state.clone(),
                                     config.clone(),
                                     ban_release_store.clone(),
+                                    captcha_store.clone(),
                                 )
                             }
                         },
@@ -167,6 +269,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
             let state = state.clone();
             let config = config.clone();
             let ban_release_store = ban_release_store.clone();
+            let captcha_store = captcha_store.clone();
             move |bot: Bot, update: teloxide::types::ChatMemberUpdated| {
                 on_chat_member_updated(
                     bot,
@@ -174,6 +277,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                     state.clone(),
                     config.clone(),
                     ban_release_store.clone(),
+                    captcha_store.clone(),
                 )
             }
         }))
@@ -181,6 +285,7 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
             let state = state.clone();
             let config = config.clone();
             let ban_release_store = ban_release_store.clone();
+            let captcha_store = captcha_store.clone();
             move |bot: Bot, query: teloxide::types::CallbackQuery| {
                 on_callback_query(
                     bot,
@@ -188,6 +293,39 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                     state.clone(),
                     config.clone(),
                     ban_release_store.clone(),
+                    captcha_store.clone(),
+                )
+            }
+        }))
+        .branch(Update::filter_chat_join_request().endpoint({
+            let state = state.clone();
+            let config = config.clone();
+            let ban_release_store = ban_release_store.clone();
+            let captcha_store = captcha_store.clone();
+            move |bot: Bot, request: teloxide::types::ChatJoinRequest| {
+                on_chat_join_request(
+                    bot,
+                    request,
+                    state.clone(),
+                    config.clone(),
+                    ban_release_store.clone(),
+                    captcha_store.clone(),
+                )
+            }
+        }))
+        .branch(Update::filter_poll_answer().endpoint({
+            let state = state.clone();
+            let config = config.clone();
+            let ban_release_store = ban_release_store.clone();
+            let captcha_store = captcha_store.clone();
+            move |bot: Bot, answer: teloxide::types::PollAnswer| {
+                on_poll_answer(
+                    bot,
+                    answer,
+                    state.clone(),
+                    config.clone(),
+                    ban_release_store.clone(),
+                    captcha_store.clone(),
                 )
             }
         }));
@@ -229,9 +367,29 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
                     config.webhook_listen_addr, options.url
                 ),
             );
-            let listener = webhooks::axum(bot.clone(), options)
+            let (mut listener, stop_flag, mut app) = webhooks::axum_to_router(bot.clone(), options)
                 .await
                 .map_err(|err| format!("failed to setup webhook: {err}"))?;
+            if config.captcha_webapp.is_some() {
+                app = app.merge(captcha_webapp::router(captcha_context.clone()));
+            }
+            let stop_token = listener.stop_token();
+            let address = config.webhook_listen_addr;
+            let server_config = config.clone();
+            tokio::spawn(async move {
+                let served = axum::Server::bind(&address)
+                    .serve(app.into_make_service())
+                    .with_graceful_shutdown(stop_flag)
+                    .await;
+                if let Err(err) = served {
+                    log_system_level(
+                        &server_config,
+                        LogLevel::Error,
+                        &format!("webhook server error: {err}"),
+                    );
+                    stop_token.stop();
+                }
+            });
             Dispatcher::builder(bot, handler)
                 .build()
                 .dispatch_with_listener(
@@ -244,6 +402,44 @@ async fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
     Ok(())
 }
 
+async fn run_captcha_pool_logger(config: Arc<Config>, pool: Arc<CaptchaPool>) {
+    log_system_level(
+        &config,
+        LogLevel::Info,
+        &format!(
+            "captcha pool worker started (interval {}s)",
+            CAPTCHA_POOL_LOG_INTERVAL.as_secs()
+        ),
+    );
+    let mut last = CaptchaPoolStats::default();
+    loop {
+        tokio::time::sleep(CAPTCHA_POOL_LOG_INTERVAL).await;
+        let stats = pool.stats();
+        if stats.hits == last.hits && stats.misses == last.misses {
+            continue;
+        }
+        let level = if stats.misses > last.misses {
+            LogLevel::Warn
+        } else {
+            LogLevel::Info
+        };
+        log_system_level(
+            &config,
+            level,
+            &format!(
+                "captcha pool: ready {}/{} hit rate {}% (hits={} misses={}, +{} misses)",
+                stats.ready,
+                stats.capacity,
+                stats.hit_rate_percent(),
+                stats.hits,
+                stats.misses,
+                stats.misses - last.misses
+            ),
+        );
+        last = stats;
+    }
+}
+
 async fn run_ban_release_worker(bot: Bot, config: Arc<Config>, store: Arc<BanReleaseStore>) {
     log_system_level(
         &config,
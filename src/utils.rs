@@ -77,6 +77,11 @@ pub fn sanitize_log_text(input: &str) -> String {
         .collect::<String>()
 }
 
+/// Solve time such as `2.35s` for logs.
+pub fn format_solve_time(solve_ms: i64) -> String {
+    format!("{:.2}s", solve_ms as f64 / 1000.0)
+}
+
 pub fn format_user_display(user: &teloxide::types::User) -> String {
     let first_name = sanitize_log_text(user.first_name.trim());
     let last_name = sanitize_log_text(user.last_name.as_deref().unwrap_or(""));